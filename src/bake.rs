@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use nalgebra_glm::{Mat4, Vec2, Vec3};
+use crate::fragments::Fragment;
+use crate::displacement::Displacement;
+use crate::obj::{spherical_direction, SPHERE_RADIUS};
+use crate::registry::ShaderProgram;
+use crate::screen::color::Color;
+use crate::screen::framebuffer::Framebuffer;
+use crate::shader::{FragmentShader, LightingClamp};
+use crate::texture::{Sampler, Texture};
+use crate::uniforms::{CullMode, Uniforms, Winding};
+
+// Fragments this far past the baked radius (rings, the Earth's moon) are
+// shaded procedurally
+const SURFACE_MARGIN: f32 = 1.25;
+
+pub struct BakeSettings {
+    pub width: usize,
+    pub height: usize,
+    // Distance from the origin the shader is evaluated at; noise is sampled in
+    // object space, so this must match the mesh
+    pub radius: f32,
+    // Animated shaders are frozen at this uniforms.time
+    pub time: u32,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        BakeSettings { width: 1024, height: 512, radius: SPHERE_RADIUS, time: 0 }
+    }
+}
+
+// Evaluates `program`'s fragment shader once per texel of an equirectangular
+// map (see obj::spherical_tex_coords for the layout), spread over `threads`
+// workers by row. Texels are shaded at the shader's reference intensity, so
+// the map can be relit with its lighting clamp.
+pub fn bake_equirectangular(program: &ShaderProgram, settings: &BakeSettings, threads: usize) -> Framebuffer {
+    let (width, height) = (settings.width, settings.height);
+    let lighting = program.fragment.lighting();
+    let rows_per_worker = height.div_ceil(threads.max(1));
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    thread::scope(|scope| {
+        for (chunk, texels) in framebuffer.buffer.chunks_mut(rows_per_worker * width).enumerate() {
+            scope.spawn(move || {
+                let uniforms = bake_uniforms(program, settings.time);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    let (x, y) = (i % width, chunk * rows_per_worker + i / width);
+                    // Texel centers; row 0 is the north edge
+                    let uv = Vec2::new((x as f32 + 0.5) / width as f32, 1.0 - (y as f32 + 0.5) / height as f32);
+                    let direction = spherical_direction(&uv);
+                    let fragment = Fragment::new(
+                        Vec2::new(x as f32, y as f32), Color::black(), 0.0, direction,
+                        lighting.reference, direction * settings.radius, uv,
+                    );
+                    *texel = program.fragment.shade(&fragment, &uniforms);
+                }
+            });
+        }
+    });
+    framebuffer
+}
+
+pub(crate) fn bake_uniforms(program: &ShaderProgram, time: u32) -> Uniforms {
+    Uniforms {
+        model_matrix: Mat4::identity(),
+        view_matrix: Mat4::identity(),
+        projection_matrix: Mat4::identity(),
+        viewport_matrix: Mat4::identity(),
+        light_dir: Vec3::new(0.0, 0.0, -1.0),
+        time,
+        noise: program.create_noise(),
+        cull_mode: CullMode::Back,
+        front_face: Winding::CounterClockwise,
+    }
+}
+
+// Where the bake tool writes, and the viewer looks for, a planet's map
+pub fn baked_map_path(directory: &Path, name: &str) -> PathBuf {
+    directory.join(format!("{}.png", name))
+}
+
+// Samples a baked map on the planet's sphere and hands everything further out
+// to the shader it was baked from
+pub struct BakedShader {
+    pub texture: Arc<Texture>,
+    pub sampler: Sampler,
+    pub lighting: LightingClamp,
+    pub radius: f32,
+    pub fallback: Arc<dyn FragmentShader>,
+}
+
+impl FragmentShader for BakedShader {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        if fragment.vertex_position.magnitude() > self.radius * SURFACE_MARGIN {
+            return self.fallback.shade(fragment, uniforms);
+        }
+        let color = self.texture.sample_grad(fragment.tex_coords, fragment.tex_coords_dx, fragment.tex_coords_dy, &self.sampler);
+        color * self.lighting.factor(fragment.intensity)
+    }
+
+    fn lighting(&self) -> LightingClamp {
+        self.lighting
+    }
+
+    fn displacement(&self) -> Displacement {
+        self.fallback.displacement()
+    }
+
+    fn needs_derivatives(&self) -> bool {
+        true
+    }
+}
+
+// `program` with its fragment shader replaced by a lookup into `texture`,
+// keeping its name and noise
+pub fn baked_program(program: &ShaderProgram, texture: Texture, radius: f32) -> ShaderProgram {
+    let mut baked = program.clone();
+    baked.fragment = Arc::new(BakedShader {
+        texture: Arc::new(texture),
+        sampler: Sampler::equirectangular(),
+        lighting: program.fragment.lighting(),
+        radius,
+        fallback: program.fragment.clone(),
+    });
+    baked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::ShaderRegistry;
+
+    #[test]
+    fn baked_map_matches_the_shader_at_texel_centers() {
+        let mut registry = ShaderRegistry::with_planets();
+        assert!(registry.register_directory(Path::new("assets/planets")).is_empty());
+        let settings = BakeSettings { width: 64, height: 32, ..BakeSettings::default() };
+        for name in ["sun", "mercury", "jupiter", "uranus"] {
+            let program = registry.get(name).unwrap();
+            let map = bake_equirectangular(program, &settings, 3);
+            let baked = baked_program(program, Texture::from_framebuffer(&map), settings.radius);
+
+            let uniforms = bake_uniforms(program, 0);
+            let (x, y) = (17, 9);
+            let uv = Vec2::new((x as f32 + 0.5) / 64.0, 1.0 - (y as f32 + 0.5) / 32.0);
+            let direction = spherical_direction(&uv);
+            for intensity in [0.03, 0.3, 0.8, 1.8] {
+                let fragment = Fragment::new(Vec2::zeros(), Color::black(), 0.0, direction, intensity, direction * 0.5, uv);
+                let expected = program.fragment.shade(&fragment, &uniforms);
+                let actual = baked.fragment.shade(&fragment, &uniforms);
+                for (a, e) in [(actual.r, expected.r), (actual.g, expected.g), (actual.b, expected.b)] {
+                    assert!(a.abs_diff(e) <= 2, "{} baked {:?}, shader {:?}", name, actual, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn planet_files_bake_with_their_own_lighting() {
+        let mut registry = ShaderRegistry::new();
+        assert!(registry.register_directory(Path::new("assets/planets")).is_empty());
+        let uranus = baked_program(registry.get("uranus").unwrap(), Texture::new(1, 1, vec![Color::from_hex(0x808080)]), SPHERE_RADIUS);
+        assert_eq!(uranus.fragment.lighting(), LightingClamp::new(0.1, 1.4));
+    }
+
+    #[test]
+    fn rings_fall_back_to_the_shader() {
+        let registry = ShaderRegistry::with_planets();
+        let saturn = registry.get("saturn").unwrap();
+        let baked = baked_program(saturn, Texture::new(1, 1, vec![Color::from_hex(0xff00ff)]), SPHERE_RADIUS);
+        let uniforms = bake_uniforms(saturn, 0);
+        let position = Vec3::new(0.8, 0.0, 0.0);
+        let fragment = Fragment::new(Vec2::zeros(), Color::black(), 0.0, Vec3::y(), 1.0, position, Vec2::zeros());
+        assert_eq!(baked.fragment.shade(&fragment, &uniforms).to_hex(), saturn.fragment.shade(&fragment, &uniforms).to_hex());
+    }
+}
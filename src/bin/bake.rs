@@ -0,0 +1,88 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Instant;
+
+use cg_custom_shaders::bake::{bake_equirectangular, baked_map_path, BakeSettings};
+use cg_custom_shaders::registry::ShaderRegistry;
+use cg_custom_shaders::screen::image::save_png;
+
+const USAGE: &str = "\
+Bakes planet shaders into equirectangular surface maps. The viewer and
+`render --baked <dir>` sample these maps instead of running the shaders.
+
+Usage: bake [options]
+  --planet <name>       planet to bake; may be repeated (default: all)
+  --size <WxH>          map resolution (default: 1024x512)
+  --time <n>            uniforms.time to freeze animated shaders at (default: 0)
+  --output-dir <dir>    where <planet>.png is written (default: assets/baked)
+  --graphs              bake the node graph versions of the built-in shaders
+  --list                print the available planets";
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut settings = BakeSettings::default();
+    let mut planets = Vec::new();
+    let mut output_dir = PathBuf::from("./assets/baked");
+    let mut graphs = false;
+    let mut list = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--planet" => planets.push(value()?),
+            "--size" => (settings.width, settings.height) = parse_size(&value()?)?,
+            "--time" => settings.time = value()?.parse().map_err(|_| "invalid --time")?,
+            "--output-dir" => output_dir = PathBuf::from(value()?),
+            "--graphs" => graphs = true,
+            "--list" => list = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+
+    let mut registry = if graphs { ShaderRegistry::with_planet_graphs() } else { ShaderRegistry::with_planets() };
+    for error in registry.register_directory(Path::new("./assets/planets")) {
+        eprintln!("warning: {}", error);
+    }
+    if list {
+        for name in registry.names() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    if planets.is_empty() {
+        planets = registry.names().map(str::to_string).collect();
+    }
+
+    std::fs::create_dir_all(&output_dir).map_err(|e| format!("{}: {}", output_dir.display(), e))?;
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    for name in &planets {
+        let program = registry.get(name).ok_or_else(|| format!("unknown planet '{}'", name))?;
+        let start = Instant::now();
+        let map = bake_equirectangular(program, &settings, threads);
+        let path = baked_map_path(&output_dir, name);
+        save_png(&map, &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("wrote {} in {:.2}s", path.display(), start.elapsed().as_secs_f32());
+    }
+    Ok(())
+}
+
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let (width, height) = value.split_once('x').ok_or_else(|| format!("expected WxH but got '{}'", value))?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("invalid size '{}'", value)),
+    }
+}
@@ -0,0 +1,108 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+
+use cg_custom_shaders::displacement::displace;
+use cg_custom_shaders::export::{export_mesh, ExportFormat};
+use cg_custom_shaders::mesh::Mesh;
+use cg_custom_shaders::obj::SPHERE_RADIUS;
+use cg_custom_shaders::primitives::icosphere;
+use cg_custom_shaders::registry::ShaderRegistry;
+
+const USAGE: &str = "\
+Exports a planet as a sphere raised and sunk by its noise (Earth's continents,
+Mercury's craters), with its shader's colors on the vertices, for 3D printing
+or other 3D tools.
+
+Usage: export [options]
+  --planet <name>       planet to export (default: earth)
+  --output <file>       .obj, .ply or .stl file to write (default: <planet>.obj)
+  --subdivisions <n>    icosphere subdivisions, each one quadruples the
+                        triangles (default: 6, at most 8)
+  --amplitude <f>       largest height as a fraction of the radius
+                        (default: chosen per planet)
+  --radius <r>          radius of the exported sphere (default: 0.5)
+  --graphs              color with the node graph versions of the built-in shaders
+  --list                print the available planets";
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut planet = "earth".to_string();
+    let mut output = None;
+    let mut subdivisions = 6;
+    let mut amplitude = None;
+    let mut radius = SPHERE_RADIUS;
+    let mut graphs = false;
+    let mut list = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--planet" => planet = value()?,
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--subdivisions" => subdivisions = value()?.parse().map_err(|_| "invalid --subdivisions")?,
+            "--amplitude" => amplitude = Some(value()?.parse::<f32>().map_err(|_| "invalid --amplitude")?),
+            "--radius" => radius = value()?.parse().map_err(|_| "invalid --radius")?,
+            "--graphs" => graphs = true,
+            "--list" => list = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+
+    let mut registry = if graphs { ShaderRegistry::with_planet_graphs() } else { ShaderRegistry::with_planets() };
+    for error in registry.register_directory(Path::new("./assets/planets")) {
+        eprintln!("warning: {}", error);
+    }
+    if list {
+        for name in registry.names() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    if subdivisions > 8 {
+        return Err(format!("--subdivisions {} is too fine, use at most 8", subdivisions));
+    }
+    if radius <= 0.0 {
+        return Err("--radius must be positive".to_string());
+    }
+    if amplitude.is_some_and(|amplitude| !(0.0..1.0).contains(&amplitude)) {
+        return Err("--amplitude must be at least 0 and below 1".to_string());
+    }
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.obj", planet)));
+    if ExportFormat::from_path(&output).is_none() {
+        return Err(format!("can't tell the format of '{}'; use .obj, .ply or .stl", output.display()));
+    }
+
+    let program = registry.get(&planet).ok_or_else(|| format!("unknown planet '{}'", planet))?;
+    let mut displacement = program.fragment.displacement();
+    if let Some(amplitude) = amplitude {
+        displacement = displacement.with_amplitude(amplitude);
+    }
+
+    let start = Instant::now();
+    // The noise is sampled in object space, so displace at the size the
+    // shaders were written for and scale afterwards
+    let sphere = Mesh::from_triangles(&icosphere(SPHERE_RADIUS, subdivisions));
+    let mut mesh = displace(&sphere, program, &displacement);
+    for vertex in &mut mesh.vertices {
+        vertex.position *= radius / SPHERE_RADIUS;
+    }
+    export_mesh(&mesh, &output).map_err(|e| e.to_string())?;
+    println!(
+        "wrote {} ({} triangles) in {:.2}s",
+        output.display(), mesh.triangle_count(), start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use nalgebra_glm::Vec3;
+use cg_custom_shaders::obj::PlanetMeshes;
+use cg_custom_shaders::offscreen::{render_offscreen, OffscreenSettings};
+use cg_custom_shaders::registry::ShaderRegistry;
+use cg_custom_shaders::screen::framebuffer::Framebuffer;
+use cg_custom_shaders::screen::image::{numbered_path, save_gif, save_image};
+use cg_custom_shaders::turntable::{render_turntable, TurntableSettings};
+
+const USAGE: &str = "\
+Renders a planet without opening a window.
+
+Usage: render [options]
+  --planet <name>       shader program to render (default: earth)
+  --eye <x,y,z>         camera position (default: 0,1,-3)
+  --center <x,y,z>      camera target (default: 0,0,0)
+  --size <WxH>          output resolution (default: 800x600)
+  --frame <n>           frame number, drives uniforms.time (default: 0)
+  --output <file>       .png or .ppm file to write; may be repeated
+                        (default: <planet>_<frame>.png and .ppm)
+  --baked <dir>         sample the maps written by `bake` from <dir> instead
+                        of running the shaders they were baked from
+  --generated           use generated sphere, ring and moon meshes instead of
+                        the OBJ models in assets/3d_models
+  --graphs              use the node graph versions of the built-in shaders
+  --list                print the available planets
+
+Turntable export:
+  --frames <n>          render n frames while the camera orbits the planet
+  --time-step <n>       frame numbers to advance per rendered frame (default: 1)
+  --orbit <degrees>     total camera orbit over the animation (default: 360)
+  --delay <ms>          GIF frame delay (default: 40)
+  With --frames, a .gif output is an animated GIF and .png/.ppm outputs
+  become numbered sequences (earth.png -> earth_0000.png, earth_0001.png, ...)";
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut turntable = TurntableSettings::default();
+    let mut settings = OffscreenSettings::default();
+    let mut frames = None;
+    let mut delay_ms = 40;
+    let mut outputs = Vec::new();
+    let mut generated = false;
+    let mut graphs = false;
+    let mut baked = None;
+    let mut list = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--planet" => settings.planet = value()?,
+            "--eye" => settings.eye = parse_vec3(&value()?)?,
+            "--center" => settings.center = parse_vec3(&value()?)?,
+            "--size" => (settings.width, settings.height) = parse_size(&value()?)?,
+            "--frame" => settings.frame = value()?.parse().map_err(|_| "invalid --frame")?,
+            "--output" => outputs.push(PathBuf::from(value()?)),
+            "--frames" => frames = Some(value()?.parse().map_err(|_| "invalid --frames")?),
+            "--time-step" => turntable.time_step = value()?.parse().map_err(|_| "invalid --time-step")?,
+            "--orbit" => turntable.orbit_degrees = value()?.parse().map_err(|_| "invalid --orbit")?,
+            "--delay" => delay_ms = value()?.parse().map_err(|_| "invalid --delay")?,
+            "--generated" => generated = true,
+            "--graphs" => graphs = true,
+            "--baked" => baked = Some(PathBuf::from(value()?)),
+            "--list" => list = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("unknown argument '{}'", arg)),
+        }
+    }
+
+    let mut registry = if graphs { ShaderRegistry::with_planet_graphs() } else { ShaderRegistry::with_planets() };
+    for error in registry.register_directory(Path::new("./assets/planets")) {
+        eprintln!("warning: {}", error);
+    }
+    let textures = Path::new("./assets/textures");
+    if textures.is_dir() {
+        if let Err(error) = registry.register_textures(textures) {
+            eprintln!("warning: {}", error);
+        }
+    }
+    if let Some(directory) = baked {
+        registry.use_baked_maps(&directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    }
+    if list {
+        for name in registry.names() {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    if frames == Some(0) {
+        return Err("--frames must be at least 1".to_string());
+    }
+
+    if outputs.is_empty() {
+        let stem = format!("{}_{}", settings.planet, settings.frame);
+        let extensions: &[&str] = if frames.is_some() { &["gif"] } else { &["png", "ppm"] };
+        for extension in extensions {
+            outputs.push(PathBuf::from(format!("{}.{}", stem, extension)));
+        }
+    }
+
+    let meshes = if generated {
+        PlanetMeshes::generate()
+    } else {
+        PlanetMeshes::load("./assets/3d_models").map_err(|e| format!("failed to load obj: {}", e))?
+    };
+    match frames {
+        Some(frames) => {
+            turntable.start = settings;
+            turntable.frames = frames;
+            let rendered = render_turntable(&turntable, &registry, &meshes)?;
+            for output in &outputs {
+                write_animation(&rendered, output, delay_ms)?;
+            }
+        }
+        None => {
+            let framebuffer = render_offscreen(&settings, &registry, &meshes)?;
+            for output in &outputs {
+                save_image(&framebuffer, output).map_err(|e| format!("{}: {}", output.display(), e))?;
+                println!("wrote {}", output.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_animation(frames: &[Framebuffer], output: &Path, delay_ms: u32) -> Result<(), String> {
+    if output.extension().and_then(|extension| extension.to_str()) == Some("gif") {
+        save_gif(frames, output, delay_ms).map_err(|e| format!("{}: {}", output.display(), e))?;
+        println!("wrote {} ({} frames)", output.display(), frames.len());
+        return Ok(());
+    }
+
+    for (index, framebuffer) in frames.iter().enumerate() {
+        let path = numbered_path(output, index);
+        save_image(framebuffer, &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    println!("wrote {} ... {}", numbered_path(output, 0).display(), numbered_path(output, frames.len().saturating_sub(1)).display());
+    Ok(())
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let parts: Vec<f32> = value.split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid vector '{}'", value))?;
+    match parts.as_slice() {
+        [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err(format!("expected x,y,z but got '{}'", value)),
+    }
+}
+
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let (width, height) = value.split_once('x').ok_or_else(|| format!("expected WxH but got '{}'", value))?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("invalid size '{}'", value)),
+    }
+}
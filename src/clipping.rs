@@ -0,0 +1,138 @@
+use nalgebra_glm::{Vec3, Vec4};
+use crate::uniforms::Uniforms;
+use crate::vertex::Vertex;
+
+// The six view frustum planes in homogeneous clip space, as (axis, sign):
+// a vertex is inside when sign * clip[axis] <= clip.w
+const FRUSTUM_PLANES: [(usize, f32); 6] = [
+    (2, -1.0), // near
+    (2, 1.0),  // far
+    (0, -1.0), // left
+    (0, 1.0),  // right
+    (1, -1.0), // bottom
+    (1, 1.0),  // top
+];
+
+fn plane_distance(clip: &Vec4, (axis, sign): (usize, f32)) -> f32 {
+    clip.w - sign * clip[axis]
+}
+
+pub fn is_inside_frustum(clip: &Vec4) -> bool {
+    FRUSTUM_PLANES.iter().all(|plane| plane_distance(clip, *plane) >= 0.0)
+}
+
+// Clips a triangle against the view frustum (Sutherland–Hodgman) and
+// re-triangulates the resulting convex polygon as a fan
+pub fn clip_triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<[Vertex; 3]> {
+    if is_inside_frustum(&v1.clip_position)
+        && is_inside_frustum(&v2.clip_position)
+        && is_inside_frustum(&v3.clip_position) {
+        return vec![[v1.clone(), v2.clone(), v3.clone()]];
+    }
+
+    let mut polygon = vec![v1.clone(), v2.clone(), v3.clone()];
+    for plane in FRUSTUM_PLANES {
+        polygon = clip_polygon(&polygon, plane);
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+    }
+
+    let mut triangles = Vec::new();
+    for i in 1..polygon.len() - 1 {
+        triangles.push([polygon[0].clone(), polygon[i].clone(), polygon[i + 1].clone()]);
+    }
+    triangles
+}
+
+fn clip_polygon(polygon: &[Vertex], plane: (usize, f32)) -> Vec<Vertex> {
+    let mut clipped = Vec::new();
+    for i in 0..polygon.len() {
+        let current = &polygon[i];
+        let next = &polygon[(i + 1) % polygon.len()];
+        let current_distance = plane_distance(&current.clip_position, plane);
+        let next_distance = plane_distance(&next.clip_position, plane);
+
+        if current_distance >= 0.0 {
+            clipped.push(current.clone());
+        }
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            clipped.push(lerp_vertex(current, next, t));
+        }
+    }
+    clipped
+}
+
+// Clip space is still linear in the vertex attributes, so a plain lerp is correct here
+fn lerp_vertex(a: &Vertex, b: &Vertex, t: f32) -> Vertex {
+    Vertex {
+        position: a.position + (b.position - a.position) * t,
+        normal: a.normal + (b.normal - a.normal) * t,
+        tex_coords: a.tex_coords + (b.tex_coords - a.tex_coords) * t,
+        color: a.color.lerp(&b.color, t),
+        clip_position: a.clip_position + (b.clip_position - a.clip_position) * t,
+        transformed_position: a.transformed_position,
+        transformed_normal: a.transformed_normal + (b.transformed_normal - a.transformed_normal) * t,
+        inv_w: a.inv_w,
+    }
+}
+
+// Perspective division and viewport mapping, run once a vertex is known to be inside the frustum
+pub fn project_to_screen(vertex: &mut Vertex, uniforms: &Uniforms) {
+    let clip = vertex.clip_position;
+    let inv_w = 1.0 / clip.w;
+    let ndc_position = Vec4::new(clip.x * inv_w, clip.y * inv_w, clip.z * inv_w, 1.0);
+    let screen_position = uniforms.viewport_matrix * ndc_position;
+
+    vertex.transformed_position = Vec3::new(screen_position.x, screen_position.y, screen_position.z);
+    vertex.inv_w = inv_w;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+
+    fn clip_vertex(x: f32, y: f32, z: f32, w: f32) -> Vertex {
+        let mut vertex = Vertex::new(Vec3::new(x, y, z), Vec3::new(0.0, 0.0, 1.0), Vec2::new(0.0, 0.0));
+        vertex.clip_position = Vec4::new(x, y, z, w);
+        vertex
+    }
+
+    #[test]
+    fn triangle_inside_frustum_is_untouched() {
+        let triangles = clip_triangle(
+            &clip_vertex(0.0, 0.0, 0.5, 1.0),
+            &clip_vertex(0.5, 0.0, 0.5, 1.0),
+            &clip_vertex(0.0, 0.5, 0.5, 1.0),
+        );
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(triangles[0][1].clip_position, Vec4::new(0.5, 0.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn triangle_crossing_near_plane_is_split() {
+        // One vertex behind the camera (negative w), two in front
+        let triangles = clip_triangle(
+            &clip_vertex(0.0, 0.0, -2.0, -1.0),
+            &clip_vertex(0.5, 0.0, 0.5, 1.0),
+            &clip_vertex(-0.5, 0.0, 0.5, 1.0),
+        );
+        assert_eq!(triangles.len(), 2);
+        for vertex in triangles.iter().flatten() {
+            assert!(vertex.clip_position.w > 0.0);
+            assert!(FRUSTUM_PLANES.iter().all(|plane| plane_distance(&vertex.clip_position, *plane) >= -1e-5));
+        }
+    }
+
+    #[test]
+    fn triangle_behind_camera_is_discarded() {
+        let triangles = clip_triangle(
+            &clip_vertex(0.0, 0.0, -2.0, -1.0),
+            &clip_vertex(0.5, 0.0, -2.0, -1.0),
+            &clip_vertex(-0.5, 0.0, -2.0, -1.0),
+        );
+        assert!(triangles.is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+use fastnoise_lite::FastNoiseLite;
+use nalgebra_glm::{Vec2, Vec3};
+use crate::bake::bake_uniforms;
+use crate::fragments::Fragment;
+use crate::mesh::Mesh;
+use crate::obj::smooth_normals;
+use crate::registry::ShaderProgram;
+use crate::shader::{earth_land, EARTH_LAND_LEVEL, MERCURY_CRATER_FLOOR, MERCURY_CRATER_RIM, SURFACE_SCALE};
+
+// The shape a planet's noise gives its surface. Each shader reports its
+// own through FragmentShader::displacement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Relief {
+    // Land raised above a flat sea, along the Earth shader's coastlines
+    Continents,
+    // Bowls with raised rims where the crater noise, sampled at `zoom`, is
+    // below the Mercury shader's crater levels
+    Craters { zoom: f32 },
+    // Rolling hills following the noise sampled at `zoom`
+    Hills { zoom: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Displacement {
+    pub relief: Relief,
+    // Largest offset, as a fraction of the distance from the center
+    pub amplitude: f32,
+}
+
+// Gentle hills, for planets whose shaders draw no landforms
+impl Default for Displacement {
+    fn default() -> Self {
+        Displacement { relief: Relief::Hills { zoom: 1.0 }, amplitude: 0.015 }
+    }
+}
+
+impl Displacement {
+    pub fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    // Offset of an object-space surface point, from -1 to 1 times the amplitude
+    pub fn height(&self, noise: &FastNoiseLite, position: &Vec3) -> f32 {
+        let p = position * SURFACE_SCALE;
+        let sample = |zoom: f32| ((noise.get_noise_3d(p.x * zoom, p.y * zoom, p.z * zoom) + 1.0) / 2.0).clamp(0.0, 1.0);
+        let height = match self.relief {
+            // The Earth shader's land, highest furthest from the coast
+            Relief::Continents => ((EARTH_LAND_LEVEL - earth_land(noise, p)) / EARTH_LAND_LEVEL).max(0.0),
+            // The Mercury shader darkens crater floors and draws rims just
+            // above them; rims peak halfway between the two levels
+            Relief::Craters { zoom } => {
+                let (floor, rim) = (MERCURY_CRATER_FLOOR, MERCURY_CRATER_RIM);
+                match sample(zoom) {
+                    n if n < floor => -(floor - n) / floor,
+                    n if n < rim => 0.15 * (1.0 - (n - (floor + rim) / 2.0).abs() / ((rim - floor) / 2.0)),
+                    _ => 0.0,
+                }
+            }
+            Relief::Hills { zoom } => sample(zoom) * 2.0 - 1.0,
+        };
+        height * self.amplitude
+    }
+}
+
+// Moves every vertex of `mesh` out from the origin by the height of the
+// planet's noise at that point, recomputes smooth normals and colors each
+// vertex with the planet's shader, lit as the bake tool lights its maps
+pub fn displace(mesh: &Mesh, program: &ShaderProgram, displacement: &Displacement) -> Mesh {
+    let uniforms = bake_uniforms(program, 0);
+    let lighting = program.fragment.lighting();
+
+    let mut vertices = mesh.vertices.clone();
+    for vertex in &mut vertices {
+        let fragment = Fragment::new(
+            Vec2::zeros(), vertex.color, 0.0, vertex.normal,
+            lighting.reference, vertex.position, vertex.tex_coords,
+        );
+        vertex.color = program.fragment.shade(&fragment, &uniforms);
+        vertex.position *= 1.0 + displacement.height(&uniforms.noise, &vertex.position);
+    }
+
+    // Vertices split along texture seams land on the same spot and get the
+    // same normal, so the surface stays closed
+    let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.position).collect();
+    for (vertex, normal) in vertices.iter_mut().zip(smooth_normals(&positions, &mesh.indices)) {
+        vertex.normal = normal;
+    }
+    Mesh { vertices, indices: mesh.indices.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::MESH_COLOR;
+    use crate::planet_definition::PlanetDefinition;
+    use crate::primitives::icosphere;
+    use crate::registry::ShaderRegistry;
+
+    #[test]
+    fn earth_land_rises_above_a_flat_sea() {
+        let sphere = Mesh::from_triangles(&icosphere(0.5, 4));
+        let earth = ShaderRegistry::with_planets().get("earth").unwrap().clone();
+        let displacement = earth.fragment.displacement();
+        let displaced = displace(&sphere, &earth, &displacement);
+        assert_eq!(displaced.indices, sphere.indices);
+
+        let (mut land, mut sea) = (0, 0);
+        for (before, after) in sphere.vertices.iter().zip(&displaced.vertices) {
+            let rise = after.position.magnitude() / before.position.magnitude() - 1.0;
+            assert!((-1e-6..=displacement.amplitude + 1e-6).contains(&rise));
+            if rise > 0.0 { land += 1 } else { sea += 1 }
+            assert!((after.normal.magnitude() - 1.0).abs() < 1e-4);
+            assert!(after.normal.dot(&after.position) > 0.0);
+            assert_ne!(after.color.to_hex(), MESH_COLOR);
+        }
+        assert!(land > 0 && sea > 0, "{} land and {} sea vertices", land, sea);
+
+        // Seam copies stay together
+        for (i, a) in sphere.vertices.iter().enumerate() {
+            for (j, b) in sphere.vertices.iter().enumerate().skip(i + 1) {
+                if a.position == b.position {
+                    assert_eq!(displaced.vertices[i].position, displaced.vertices[j].position);
+                    assert_eq!(displaced.vertices[i].normal, displaced.vertices[j].normal);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn craters_sink_and_rims_rise() {
+        let mercury = ShaderRegistry::with_planets().get("mercury").unwrap().clone();
+        let noise = mercury.create_noise();
+        let craters = mercury.fragment.displacement();
+        assert!(matches!(craters.relief, Relief::Craters { .. }));
+        let heights: Vec<f32> = icosphere(0.5, 3).iter().map(|vertex| craters.height(&noise, &vertex.position)).collect();
+        assert!(heights.iter().any(|&height| height < 0.0));
+        assert!(heights.iter().any(|&height| height > 0.0));
+        assert!(heights.iter().all(|height| height.abs() <= craters.amplitude));
+    }
+
+    #[test]
+    fn planet_files_follow_their_own_noise() {
+        let definition = PlanetDefinition::parse(
+            "name = \"earth\"\n[[layers]]\nscale = 4.0\nramp = [{ at = 0.0, color = \"#ffffff\" }]",
+        ).unwrap();
+        let mut registry = ShaderRegistry::with_planets();
+        registry.register(definition.into_program());
+        let relief = registry.get("earth").unwrap().fragment.displacement().relief;
+        assert_eq!(relief, Relief::Hills { zoom: 4.0 });
+    }
+}
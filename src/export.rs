@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use nalgebra_glm::Vec3;
+use crate::mesh::Mesh;
+use crate::screen::color::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    // Wavefront OBJ with vertex colors after each position, as MeshLab and
+    // Blender read them
+    Obj,
+    // ASCII PLY with normals and colors per vertex
+    Ply,
+    // Binary STL. It only has face normals, and face colors in the
+    // VisCAM/SolidView attribute bits, averaged from the vertices.
+    Stl,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "obj" => Some(ExportFormat::Obj),
+            "ply" => Some(ExportFormat::Ply),
+            "stl" => Some(ExportFormat::Stl),
+            _ => None,
+        }
+    }
+}
+
+// Writes `mesh` in the format its extension names
+pub fn export_mesh(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let format = ExportFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{}: expected a .obj, .ply or .stl file", path.display()))
+    })?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_mesh(mesh, format, &mut writer)?;
+    writer.flush()
+}
+
+pub fn write_mesh<W: Write>(mesh: &Mesh, format: ExportFormat, writer: &mut W) -> io::Result<()> {
+    match format {
+        ExportFormat::Obj => write_obj(mesh, writer),
+        ExportFormat::Ply => write_ply(mesh, writer),
+        ExportFormat::Stl => write_stl(mesh, writer),
+    }
+}
+
+fn unit_channels(color: &Color) -> [f32; 3] {
+    [color.r, color.g, color.b].map(|channel| channel as f32 / 255.0)
+}
+
+pub fn write_obj<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    for vertex in &mesh.vertices {
+        let (p, [r, g, b]) = (vertex.position, unit_channels(&vertex.color));
+        writeln!(writer, "v {} {} {} {:.4} {:.4} {:.4}", p.x, p.y, p.z, r, g, b)?;
+    }
+    for vertex in &mesh.vertices {
+        writeln!(writer, "vt {} {}", vertex.tex_coords.x, vertex.tex_coords.y)?;
+    }
+    for vertex in &mesh.vertices {
+        writeln!(writer, "vn {} {} {}", vertex.normal.x, vertex.normal.y, vertex.normal.z)?;
+    }
+    // OBJ indices start at 1, and every vertex has its own vt and vn
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + 1);
+        writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+    Ok(())
+}
+
+pub fn write_ply<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "ply\nformat ascii 1.0")?;
+    writeln!(writer, "element vertex {}", mesh.vertices.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz"] {
+        writeln!(writer, "property float {}", property)?;
+    }
+    for property in ["red", "green", "blue"] {
+        writeln!(writer, "property uchar {}", property)?;
+    }
+    writeln!(writer, "element face {}", mesh.triangle_count())?;
+    writeln!(writer, "property list uchar int vertex_indices\nend_header")?;
+
+    for vertex in &mesh.vertices {
+        let (p, n, c) = (vertex.position, vertex.normal, vertex.color);
+        writeln!(writer, "{} {} {} {} {} {} {} {} {}", p.x, p.y, p.z, n.x, n.y, n.z, c.r, c.g, c.b)?;
+    }
+    for triangle in mesh.indices.chunks_exact(3) {
+        writeln!(writer, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?;
+    }
+    Ok(())
+}
+
+pub fn write_stl<W: Write>(mesh: &Mesh, writer: &mut W) -> io::Result<()> {
+    let mut header = [b' '; 80];
+    let title = b"cg_custom_shaders mesh";
+    header[..title.len()].copy_from_slice(title);
+    writer.write_all(&header)?;
+    writer.write_all(&(mesh.triangle_count() as u32).to_le_bytes())?;
+
+    let write_vec3 = |writer: &mut W, v: &Vec3| -> io::Result<()> {
+        for component in [v.x, v.y, v.z] {
+            writer.write_all(&component.to_le_bytes())?;
+        }
+        Ok(())
+    };
+    for triangle in mesh.triangles() {
+        let [a, b, c] = triangle.map(|vertex| vertex.position);
+        let normal = (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_else(Vec3::zeros);
+        write_vec3(writer, &normal)?;
+        for position in [a, b, c] {
+            write_vec3(writer, &position)?;
+        }
+
+        // Bit 15 marks the color as set, then five bits each of red, green
+        // and blue from the top down
+        let average = |channel: fn(&Color) -> u8| {
+            triangle.iter().map(|vertex| channel(&vertex.color) as u16).sum::<u16>() / 3
+        };
+        let [r, g, b] = [average(|c| c.r), average(|c| c.g), average(|c| c.b)].map(|channel| channel >> 3);
+        writer.write_all(&(0x8000 | r << 10 | g << 5 | b).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::Obj;
+    use crate::primitives::uv_sphere;
+
+    fn colored_sphere() -> Mesh {
+        let mut mesh = Mesh::from_triangles(&uv_sphere(0.5, 8, 4));
+        for (i, vertex) in mesh.vertices.iter_mut().enumerate() {
+            vertex.color = Color::new(i as i32 * 7, 128, 255);
+        }
+        mesh
+    }
+
+    #[test]
+    fn obj_export_loads_back() {
+        let mesh = colored_sphere();
+        let path = std::env::temp_dir().join(format!("export-{}.obj", std::process::id()));
+        export_mesh(&mesh, &path).unwrap();
+        let obj = Obj::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = obj.get_mesh();
+        assert_eq!(loaded.triangle_count(), mesh.triangle_count());
+        for (original, read) in mesh.triangles().zip(loaded.triangles()) {
+            for (a, b) in original.iter().zip(read) {
+                assert!((a.position - b.position).magnitude() < 1e-6);
+                assert!((a.normal - b.normal).magnitude() < 1e-6);
+                assert_eq!(a.tex_coords, b.tex_coords);
+            }
+        }
+    }
+
+    #[test]
+    fn ply_and_stl_hold_every_triangle() {
+        let mesh = colored_sphere();
+
+        let mut ply = Vec::new();
+        write_mesh(&mesh, ExportFormat::Ply, &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.contains(&format!("element vertex {}\n", mesh.vertices.len())));
+        assert!(header.contains(&format!("element face {}\n", mesh.triangle_count())));
+        assert_eq!(body.lines().count(), mesh.vertices.len() + mesh.triangle_count());
+        assert!(body.lines().nth(1).unwrap().ends_with(" 7 128 255"));
+
+        let mut stl = Vec::new();
+        write_mesh(&mesh, ExportFormat::Stl, &mut stl).unwrap();
+        assert_eq!(stl.len(), 84 + 50 * mesh.triangle_count());
+        assert_eq!(u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize, mesh.triangle_count());
+        // The first facet's normal points away from the center
+        let float = |offset: usize| f32::from_le_bytes(stl[offset..offset + 4].try_into().unwrap());
+        let normal = Vec3::new(float(84), float(88), float(92));
+        let corner = Vec3::new(float(96), float(100), float(104));
+        assert!(normal.dot(&corner) > 0.0);
+        assert_eq!(u16::from_le_bytes([stl[132], stl[133]]) & 0x8000, 0x8000);
+
+        assert_eq!(ExportFormat::from_path(Path::new("earth.STL")), Some(ExportFormat::Stl));
+        assert_eq!(ExportFormat::from_path(Path::new("earth.png")), None);
+    }
+}
@@ -3,29 +3,45 @@ use core::f32;
 use nalgebra_glm::{dot, Vec2, Vec3};
 use crate::bounding_box::{barycentric_coordinates, calculate_bounding_box, edge_function};
 use crate::screen::color::Color;
+use crate::screen::framebuffer::Framebuffer;
 use crate::uniforms::Uniforms;
 use crate::vertex::Vertex;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Fragment {
     pub position: Vec2,
     pub color: Color,
     pub depth: f32,
     pub normal: Vec3,
-    pub intensity: f32
+    pub intensity: f32,
+    pub vertex_position: Vec3,
+    pub tex_coords: Vec2,
+    // Change of tex_coords one pixel to the right and one pixel down
+    pub tex_coords_dx: Vec2,
+    pub tex_coords_dy: Vec2,
 }
 
 
 impl Fragment {
-    pub fn new(x: f32, y: f32, color: Color, depth: f32, normal:Vec3, intensity: f32) -> Self {
+    pub fn new(position: Vec2, color: Color, depth: f32, normal:Vec3, intensity: f32, vertex_position: Vec3, tex_coords: Vec2) -> Self {
         Fragment {
-            position: Vec2::new(x, y),
+            position,
             color,
             depth,
             normal,
-            intensity
+            intensity,
+            vertex_position,
+            tex_coords,
+            tex_coords_dx: Vec2::zeros(),
+            tex_coords_dy: Vec2::zeros(),
         }
     }
+
+    pub fn with_tex_coord_derivatives(mut self, dx: Vec2, dy: Vec2) -> Self {
+        self.tex_coords_dx = dx;
+        self.tex_coords_dy = dy;
+        self
+    }
 }
 
 // pub fn line(a: &Vertex, b: &Vertex) -> Vec<Fragment>{
@@ -79,35 +95,209 @@ impl Fragment {
 //     fragments
 // }
 
-pub fn triangle_fill(v1: &Vertex, v2:&Vertex ,v3:&Vertex, uniforms: &Uniforms)-> Vec<Fragment>{
-    let mut fragments = Vec::new();
+// Rasterizes a triangle into `framebuffer`, whose top-left pixel sits at
+// `origin` in screen space. Each covered pixel that passes the early depth
+// test is handed to `fragment_stage`, and the returned color is written.
+// Texture coordinate derivatives are only filled in with `derivatives`.
+pub fn triangle_fill<F>([v1, v2, v3]: [&Vertex; 3], uniforms: &Uniforms, framebuffer: &mut Framebuffer, origin: (usize, usize), derivatives: bool, mut fragment_stage: F)
+where
+    F: FnMut(&Fragment) -> Color,
+{
     let (a,b,c) = (v1.transformed_position,v2.transformed_position, v3.transformed_position);
 
     let (min_x, min_y, max_x, max_y) = calculate_bounding_box(&a, &b, &c);
+    let (origin_x, origin_y) = (origin.0 as i32, origin.1 as i32);
+    let (min_x, min_y) = (min_x.max(origin_x), min_y.max(origin_y));
+    let max_x = max_x.min(origin_x + framebuffer.width as i32);
+    let max_y = max_y.min(origin_y + framebuffer.height as i32);
 
     let triangle_area = edge_function(&a,&b,&c);
+    // Perspective-correct texture coordinates at any screen point, inside the triangle or not
+    let tex_coords_at = |x: f32, y: f32| {
+        let (w1, w2, w3) = barycentric_coordinates(&Vec3::new(x, y, 0.0), &a, &b, &c, triangle_area);
+        let (p1, p2, p3) = perspective_weights(w1, w2, w3, v1.inv_w, v2.inv_w, v3.inv_w);
+        v1.tex_coords*p1 + v2.tex_coords*p2 + v3.tex_coords*p3
+    };
     // Iterate over each pixel in the bounding box
     for y in min_y..max_y{
         for x in min_x..max_x{
             let point = Vec3::new(x as f32, y as f32, 0.0);
-            
+
             let (w1, w2, w3) = barycentric_coordinates(&point, &a, &b, &c, triangle_area);
 
-            // if w1!=0.0 || w2!=0.0 || w3!=0.0{
-                if w1>=0.0 && w1 <=1.0 &&
-                w2>=0.0 && w2 <=1.0 &&
-                w3>=0.0 && w3 <=1.0 {
-                    let color = Color::new(100, 100, 100);
-                    let depth = a.z*w1 +b.z*w2 + c.z*w3;
-                    let normal = v1.transformed_normal*w1+v2.transformed_normal *w2 + v3.transformed_normal*w3;
-                    let normal = normal.normalize();
-                    let intensity = dot(&normal, &uniforms.light_dir);
-                    fragments.push(
-                        Fragment::new(x as f32, y as f32, color, depth, normal, intensity)
-                    );
+            if (0.0..=1.0).contains(&w1) &&
+            (0.0..=1.0).contains(&w2) &&
+            (0.0..=1.0).contains(&w3) {
+                // Screen-space z is already z/w, so depth stays affine
+                let depth = a.z*w1 +b.z*w2 + c.z*w3;
+
+                // Early depth test: occluded pixels are never interpolated or shaded
+                let (local_x, local_y) = ((x - origin_x) as usize, (y - origin_y) as usize);
+                if !framebuffer.depth_test(local_x, local_y, depth) {
+                    continue;
                 }
-            // } 
+
+                // Every other varying is interpolated perspective-correct
+                let (p1, p2, p3) = perspective_weights(w1, w2, w3, v1.inv_w, v2.inv_w, v3.inv_w);
+                let color = interpolate_color(&v1.color, &v2.color, &v3.color, p1, p2, p3);
+                let normal = v1.transformed_normal*p1+v2.transformed_normal *p2 + v3.transformed_normal*p3;
+                let normal = normal.normalize();
+                let intensity = dot(&normal, &uniforms.light_dir);
+                let vertex_position = v1.position*p1 + v2.position*p2 + v3.position*p3;
+                let tex_coords = v1.tex_coords*p1 + v2.tex_coords*p2 + v3.tex_coords*p3;
+                let mut fragment = Fragment::new(Vec2::new(x as f32, y as f32), color, depth, normal, intensity, vertex_position, tex_coords);
+                if derivatives {
+                    let tex_coords_dx = tex_coords_at(x as f32 + 1.0, y as f32) - tex_coords;
+                    let tex_coords_dy = tex_coords_at(x as f32, y as f32 + 1.0) - tex_coords;
+                    fragment = fragment.with_tex_coord_derivatives(tex_coords_dx, tex_coords_dy);
+                }
+
+                let shaded_color = fragment_stage(&fragment);
+                framebuffer.set_current_color(shaded_color.to_hex());
+                framebuffer.point(local_x, local_y, depth);
+            }
+        }
+    }
+}
+
+// Turns screen-space barycentrics into weights that are linear in view space
+fn perspective_weights(w1: f32, w2: f32, w3: f32, inv_w1: f32, inv_w2: f32, inv_w3: f32) -> (f32, f32, f32) {
+    let (p1, p2, p3) = (w1*inv_w1, w2*inv_w2, w3*inv_w3);
+    let sum = p1 + p2 + p3;
+    (p1/sum, p2/sum, p3/sum)
+}
+
+fn interpolate_color(c1: &Color, c2: &Color, c3: &Color, w1: f32, w2: f32, w3: f32) -> Color {
+    Color::new(
+        (c1.r as f32*w1 + c2.r as f32*w2 + c3.r as f32*w3).round() as i32,
+        (c1.g as f32*w1 + c2.g as f32*w2 + c3.g as f32*w3).round() as i32,
+        (c1.b as f32*w1 + c2.b as f32*w2 + c3.b as f32*w3).round() as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastnoise_lite::FastNoiseLite;
+    use nalgebra_glm::{look_at, perspective, Mat4, Vec4};
+    use crate::clipping::project_to_screen;
+    use crate::shader::vertex_shader;
+    use crate::uniforms::{CullMode, Winding};
+
+    const WIDTH: f32 = 200.0;
+    const HEIGHT: f32 = 200.0;
+    const QUAD_DEPTH: f32 = 20.0;
+    const CHECKERS: f32 = 8.0;
+
+    fn grazing_uniforms() -> Uniforms {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: look_at(&Vec3::new(0.0, 0.3, -1.0), &Vec3::new(0.0, 0.0, 4.0), &Vec3::new(0.0, 1.0, 0.0)),
+            projection_matrix: perspective(1.0, 60.0f32.to_radians(), 0.1, 100.0),
+            viewport_matrix: Mat4::new(
+                WIDTH / 2.0, 0.0, 0.0, WIDTH / 2.0,
+                0.0, -HEIGHT / 2.0, 0.0, HEIGHT / 2.0,
+                0.0, 0.0, 1.0, 0.0,
+                0.0, 0.0, 0.0, 1.0
+            ),
+            light_dir: Vec3::new(0.0, 1.0, 0.0),
+            time: 0,
+            noise: FastNoiseLite::new(),
+            cull_mode: CullMode::None,
+            front_face: Winding::CounterClockwise,
         }
     }
-    fragments
+
+    // Floor quad on y = 0 receding from the camera, u across and v along it
+    fn checkerboard_quad() -> [Vertex; 4] {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        [
+            Vertex::new(Vec3::new(-1.0, 0.0, 0.0), up, Vec2::new(0.0, 0.0)),
+            Vertex::new(Vec3::new(1.0, 0.0, 0.0), up, Vec2::new(1.0, 0.0)),
+            Vertex::new(Vec3::new(1.0, 0.0, QUAD_DEPTH), up, Vec2::new(1.0, 1.0)),
+            Vertex::new(Vec3::new(-1.0, 0.0, QUAD_DEPTH), up, Vec2::new(0.0, 1.0)),
+        ]
+    }
+
+    // Casts the ray through a pixel onto the floor and returns the exact UV there
+    fn exact_uv(uniforms: &Uniforms, x: f32, y: f32) -> Vec2 {
+        let inverse = (uniforms.projection_matrix * uniforms.view_matrix).try_inverse().unwrap();
+        let ndc_x = (x - WIDTH / 2.0) / (WIDTH / 2.0);
+        let ndc_y = (y - HEIGHT / 2.0) / (-HEIGHT / 2.0);
+        let near = inverse * Vec4::new(ndc_x, ndc_y, -1.0, 1.0);
+        let far = inverse * Vec4::new(ndc_x, ndc_y, 1.0, 1.0);
+        let near = near.xyz() / near.w;
+        let far = far.xyz() / far.w;
+        let t = near.y / (near.y - far.y);
+        let hit = near + (far - near) * t;
+        Vec2::new((hit.x + 1.0) / 2.0, hit.z / QUAD_DEPTH)
+    }
+
+    fn checker(uv: &Vec2) -> bool {
+        ((uv.x * CHECKERS).floor() as i32 + (uv.y * CHECKERS).floor() as i32) % 2 == 0
+    }
+
+    #[test]
+    fn grazing_checkerboard_is_not_distorted() {
+        let uniforms = grazing_uniforms();
+        let quad: Vec<Vertex> = checkerboard_quad().iter()
+            .map(|vertex| {
+                let mut shaded = vertex_shader(vertex, &uniforms);
+                project_to_screen(&mut shaded, &uniforms);
+                shaded
+            })
+            .collect();
+
+        let mut framebuffer = Framebuffer::new(WIDTH as usize, HEIGHT as usize);
+        let mut fragments = Vec::new();
+        for triangle in [[&quad[0], &quad[1], &quad[2]], [&quad[0], &quad[2], &quad[3]]] {
+            triangle_fill(triangle, &uniforms, &mut framebuffer, (0, 0), true, |fragment| {
+                fragments.push(fragment.clone());
+                Color::black()
+            });
+        }
+        assert!(fragments.len() > 1000);
+
+        let mut mismatches = 0;
+        for fragment in &fragments {
+            let expected = exact_uv(&uniforms, fragment.position.x, fragment.position.y);
+            assert!(
+                (fragment.tex_coords - expected).abs().max() < 1e-3,
+                "uv {:?} at {:?}, expected {:?}", fragment.tex_coords, fragment.position, expected
+            );
+            let right = exact_uv(&uniforms, fragment.position.x + 1.0, fragment.position.y);
+            let below = exact_uv(&uniforms, fragment.position.x, fragment.position.y + 1.0);
+            assert!((fragment.tex_coords_dx - (right - expected)).abs().max() < 1e-3);
+            assert!((fragment.tex_coords_dy - (below - expected)).abs().max() < 1e-3);
+            if checker(&fragment.tex_coords) != checker(&expected) {
+                mismatches += 1;
+            }
+        }
+        // Only pixels sitting exactly on a checker edge may disagree
+        assert!(mismatches * 100 < fragments.len());
+    }
+
+    #[test]
+    fn hidden_fragments_are_never_shaded() {
+        let uniforms = grazing_uniforms();
+        let normal = Vec3::new(0.0, 0.0, -1.0);
+        let corner = |x: f32, y: f32, depth: f32| Vertex::new(Vec3::new(x, y, depth), normal, Vec2::zeros());
+        let mut framebuffer = Framebuffer::new(64, 64);
+
+        // The near triangle covers the top-left half of the far one
+        let mut near = Vec::new();
+        triangle_fill([&corner(0.0, 0.0, 0.2), &corner(0.0, 32.0, 0.2), &corner(32.0, 0.0, 0.2)], &uniforms, &mut framebuffer, (0, 0), false, |fragment| {
+            near.push((fragment.position.x as i32, fragment.position.y as i32));
+            Color::black()
+        });
+        let mut far = Vec::new();
+        triangle_fill([&corner(0.0, 0.0, 0.8), &corner(0.0, 64.0, 0.8), &corner(64.0, 0.0, 0.8)], &uniforms, &mut framebuffer, (0, 0), false, |fragment| {
+            far.push((fragment.position.x as i32, fragment.position.y as i32));
+            Color::black()
+        });
+
+        assert!(near.len() > 400);
+        assert!(far.len() > 1000);
+        assert!(far.iter().all(|pixel| !near.contains(pixel)));
+    }
 }
@@ -0,0 +1,344 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use ::gltf::image::Format;
+use ::gltf::mesh::Mode;
+use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use fastnoise_lite::FastNoiseLite;
+use nalgebra_glm::{mat4_to_mat3, Mat4, Vec2, Vec3, Vec4};
+use crate::mesh::Mesh;
+use crate::obj::placeholder_tex_coords;
+use crate::registry::ShaderProgram;
+use crate::scene::SceneNode;
+use crate::screen::color::Color;
+use crate::texture::{Filter, Sampler, Texture, TextureShader, Wrap};
+use crate::vertex::Vertex;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(String, ::gltf::Error),
+    NoMeshes(String),
+    // glTF allows primitives without positions, but there is nothing to draw
+    MissingPositions { file: String, mesh: String },
+    InvalidIndex { file: String, mesh: String, index: u32 },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Import(file, error) => write!(f, "{}: {}", file, error),
+            GltfError::NoMeshes(file) => write!(f, "{}: no triangle meshes", file),
+            GltfError::MissingPositions { file, mesh } => write!(f, "{}: mesh '{}' has no positions", file, mesh),
+            GltfError::InvalidIndex { file, mesh, index } => {
+                write!(f, "{}: mesh '{}' refers to missing vertex {}", file, mesh, index)
+            }
+        }
+    }
+}
+
+impl Error for GltfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GltfError::Import(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// One primitive of a mesh where a node places it. Its node's world transform
+// is already applied to the vertices, and `texture` is its base color: the
+// base color texture times the base color factor, or a single texel of the
+// factor when there is no texture.
+pub struct GltfPrimitive {
+    // The node's name, else the mesh's
+    pub name: String,
+    pub mesh: Mesh,
+    pub texture: Arc<Texture>,
+    pub sampler: Sampler,
+}
+
+impl GltfPrimitive {
+    pub fn program(&self) -> ShaderProgram {
+        let shader = TextureShader::new(self.texture.clone()).with_sampler(self.sampler);
+        ShaderProgram::new(&self.name, shader, FastNoiseLite::new)
+    }
+}
+
+// The triangles of a .gltf or .glb file's default scene (or its first one).
+// Texture coordinates are flipped to the OBJ convention the renderer uses,
+// with v running bottom to top.
+pub struct Gltf {
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+impl Gltf {
+    pub fn load(filename: &str) -> Result<Self, GltfError> {
+        let (document, buffers, images) = ::gltf::import(filename)
+            .map_err(|error| GltfError::Import(filename.to_string(), error))?;
+        let context = Context { file: filename, buffers: &buffers, images: &images };
+
+        let mut primitives = Vec::new();
+        match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => {
+                for node in scene.nodes() {
+                    context.load_node(&node, Mat4::identity(), &mut primitives)?;
+                }
+            }
+            // Files without scenes just list meshes
+            None => {
+                for mesh in document.meshes() {
+                    context.load_mesh(&mesh, None, Mat4::identity(), &mut primitives)?;
+                }
+            }
+        }
+        if primitives.is_empty() {
+            return Err(GltfError::NoMeshes(filename.to_string()));
+        }
+        Ok(Gltf { primitives })
+    }
+
+    pub fn primitive(&self, name: &str) -> Option<&GltfPrimitive> {
+        self.primitives.iter().find(|primitive| primitive.name == name)
+    }
+
+    // Every primitive in one mesh, colored by base color factor only
+    pub fn get_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::default();
+        for primitive in &self.primitives {
+            let offset = mesh.vertices.len() as u32;
+            mesh.indices.extend(primitive.mesh.indices.iter().map(|index| index + offset));
+            mesh.vertices.extend(primitive.mesh.vertices.iter().cloned());
+        }
+        mesh
+    }
+
+    // A group node with a child per primitive, each drawn with its own base
+    // color, for render_scene
+    pub fn scene_node(&self, name: &str) -> SceneNode {
+        self.primitives.iter().fold(SceneNode::new(name), |group, primitive| {
+            group.with_child(SceneNode::new(&primitive.name).with_mesh(Arc::new(primitive.mesh.clone()), primitive.program()))
+        })
+    }
+}
+
+struct Context<'a> {
+    file: &'a str,
+    buffers: &'a [::gltf::buffer::Data],
+    images: &'a [::gltf::image::Data],
+}
+
+impl Context<'_> {
+    fn load_node(&self, node: &::gltf::Node, parent: Mat4, primitives: &mut Vec<GltfPrimitive>) -> Result<(), GltfError> {
+        let world = parent * Mat4::from(node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            self.load_mesh(&mesh, node.name(), world, primitives)?;
+        }
+        for child in node.children() {
+            self.load_node(&child, world, primitives)?;
+        }
+        Ok(())
+    }
+
+    fn load_mesh(&self, mesh: &::gltf::Mesh, node_name: Option<&str>, world: Mat4, primitives: &mut Vec<GltfPrimitive>) -> Result<(), GltfError> {
+        let name = node_name.or(mesh.name()).map(str::to_string).unwrap_or_else(|| format!("mesh{}", mesh.index()));
+        let error_name = mesh.name().map(str::to_string).unwrap_or_else(|| format!("mesh{}", mesh.index()));
+
+        for primitive in mesh.primitives() {
+            // Points and lines can't be filled
+            if !matches!(primitive.mode(), Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan) {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| self.buffers.get(buffer.index()).map(|data| &data[..]));
+            let positions: Vec<Vec3> = reader.read_positions()
+                .ok_or_else(|| GltfError::MissingPositions { file: self.file.to_string(), mesh: error_name.clone() })?
+                .map(Vec3::from)
+                .collect();
+            let indices = match reader.read_indices() {
+                Some(indices) => triangulate(primitive.mode(), &indices.into_u32().collect::<Vec<_>>()),
+                None => triangulate(primitive.mode(), &(0..positions.len() as u32).collect::<Vec<_>>()),
+            };
+            if let Some(&index) = indices.iter().find(|&&index| index as usize >= positions.len()) {
+                return Err(GltfError::InvalidIndex { file: self.file.to_string(), mesh: error_name.clone(), index });
+            }
+
+            let material = primitive.material();
+            let pbr = material.pbr_metallic_roughness();
+            let [r, g, b, _] = pbr.base_color_factor();
+            let color = factor_color([r, g, b]);
+            let texture_info = pbr.base_color_texture();
+
+            let tex_coords: Vec<Vec2> = texture_info.as_ref()
+                .and_then(|info| reader.read_tex_coords(info.tex_coord()))
+                .or_else(|| reader.read_tex_coords(0))
+                .map(|coords| coords.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect())
+                .filter(|coords: &Vec<Vec2>| coords.len() == positions.len())
+                .unwrap_or_else(|| placeholder_tex_coords(&positions));
+            let normals: Option<Vec<Vec3>> = reader.read_normals()
+                .map(|normals| normals.map(Vec3::from).collect())
+                .filter(|normals: &Vec<Vec3>| normals.len() == positions.len());
+
+            let vertex = |i: usize, normal: Vec3| Vertex { color, ..Vertex::new(positions[i], normal, tex_coords[i]) };
+            let mut primitive_mesh = match normals {
+                Some(normals) => Mesh::new((0..positions.len()).map(|i| vertex(i, normals[i])).collect(), indices),
+                // glTF asks for flat normals when a primitive has none
+                None => Mesh::from_triangles(&indices.chunks_exact(3).flat_map(|triangle| {
+                    let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+                    let normal = (b - a).cross(&(c - a)).try_normalize(0.0).unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0));
+                    [0, 1, 2].map(|i| vertex(triangle[i] as usize, normal))
+                }).collect::<Vec<_>>()),
+            };
+            transform_mesh(&mut primitive_mesh, &world);
+
+            let (texture, sampler) = match texture_info {
+                Some(info) => (
+                    self.base_color_texture(info.texture().source().index(), [r, g, b]),
+                    texture_sampler(&info.texture().sampler()),
+                ),
+                None => (Texture::new(1, 1, vec![color]), Sampler::default()),
+            };
+            primitives.push(GltfPrimitive { name: name.clone(), mesh: primitive_mesh, texture: Arc::new(texture), sampler });
+        }
+        Ok(())
+    }
+
+    fn base_color_texture(&self, image: usize, factor: [f32; 3]) -> Texture {
+        let image = &self.images[image];
+        // Channels per pixel and bytes per channel
+        let (channels, size) = match image.format {
+            Format::R8 => (1, 1),
+            Format::R8G8 => (2, 1),
+            Format::R8G8B8 => (3, 1),
+            Format::R8G8B8A8 => (4, 1),
+            Format::R16 => (1, 2),
+            Format::R16G16 => (2, 2),
+            Format::R16G16B16 => (3, 2),
+            Format::R16G16B16A16 => (4, 2),
+            Format::R32G32B32FLOAT => (3, 4),
+            Format::R32G32B32A32FLOAT => (4, 4),
+        };
+        let channel = |bytes: &[u8]| match size {
+            1 => bytes[0] as f32 / 255.0,
+            2 => u16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+            _ => f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        };
+        let texels = image.pixels.chunks_exact(channels * size)
+            .map(|pixel| {
+                // One- and two-channel images are grey, with alpha in the second
+                let rgb = [0, 1, 2].map(|i| {
+                    let source = if channels < 3 { 0 } else { i };
+                    channel(&pixel[source * size..]) * factor[i]
+                });
+                factor_color(rgb)
+            })
+            .collect();
+        Texture::new(image.width as usize, image.height as usize, texels)
+    }
+}
+
+fn factor_color([r, g, b]: [f32; 3]) -> Color {
+    Color::new((r * 255.0).round() as i32, (g * 255.0).round() as i32, (b * 255.0).round() as i32)
+}
+
+fn triangulate(mode: Mode, indices: &[u32]) -> Vec<u32> {
+    match mode {
+        // Every other strip triangle is reversed to keep the winding
+        Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+            .flat_map(|i| if i % 2 == 0 { [indices[i], indices[i + 1], indices[i + 2]] } else { [indices[i + 1], indices[i], indices[i + 2]] })
+            .collect(),
+        Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+            .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+            .collect(),
+        _ => indices[..indices.len() - indices.len() % 3].to_vec(),
+    }
+}
+
+// Moves a mesh into world space. Normals use the inverse transpose so they
+// stay perpendicular under uneven scaling, and mirroring transforms reverse
+// the winding so triangles keep facing out.
+fn transform_mesh(mesh: &mut Mesh, world: &Mat4) {
+    let linear = mat4_to_mat3(world);
+    let normal_matrix = linear.try_inverse().map(|inverse| inverse.transpose()).unwrap_or(linear);
+    for vertex in &mut mesh.vertices {
+        let position = world * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0);
+        vertex.position = position.xyz();
+        vertex.normal = (normal_matrix * vertex.normal).try_normalize(0.0).unwrap_or(vertex.normal);
+        vertex.transformed_position = vertex.position;
+        vertex.transformed_normal = vertex.normal;
+    }
+    if linear.determinant() < 0.0 {
+        for triangle in mesh.indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+fn texture_sampler(sampler: &::gltf::texture::Sampler) -> Sampler {
+    // Mirrored repeat is treated as plain repeat
+    let wrap = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => Wrap::Clamp,
+        WrappingMode::MirroredRepeat | WrappingMode::Repeat => Wrap::Repeat,
+    };
+    Sampler {
+        filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => Filter::Nearest,
+            _ => Filter::Bilinear,
+        },
+        wrap_u: wrap(sampler.wrap_s()),
+        wrap_v: wrap(sampler.wrap_t()),
+        mipmaps: !matches!(sampler.min_filter(), Some(MinFilter::Nearest | MinFilter::Linear)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facing(mesh: &Mesh) -> Vec3 {
+        let [a, b, c] = mesh.triangles().next().unwrap().map(|vertex| vertex.position);
+        (b - a).cross(&(c - a)).normalize()
+    }
+
+    #[test]
+    fn loads_a_textured_quad_through_its_node_hierarchy() {
+        let gltf = Gltf::load("./assets/gltf/textured_quad.gltf").unwrap();
+        assert_eq!(gltf.primitives.len(), 1);
+        let quad = gltf.primitive("quad").unwrap();
+        assert_eq!((quad.mesh.vertices.len(), quad.mesh.triangle_count()), (4, 2));
+
+        // Scaled by the quad's node, then moved by its parent
+        assert_eq!(quad.mesh.vertices[0].position, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(quad.mesh.vertices[2].position, Vec3::new(3.0, 2.0, 0.0));
+        assert_eq!(quad.mesh.vertices[0].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((facing(&quad.mesh) - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+
+        // The bottom-left corner has glTF coordinates (0, 1)
+        assert_eq!(quad.mesh.vertices[0].tex_coords, Vec2::new(0.0, 0.0));
+        assert_eq!((quad.sampler.filter, quad.sampler.wrap_u, quad.sampler.mipmaps), (Filter::Nearest, Wrap::Clamp, false));
+        assert_eq!(quad.texture.sample(Vec2::new(0.25, 0.75), &quad.sampler).to_hex(), 0xff0000);
+        assert_eq!(quad.texture.sample(Vec2::new(0.75, 0.25), &quad.sampler).to_hex(), 0xffffff);
+    }
+
+    #[test]
+    fn fills_in_normals_and_keeps_mirrored_nodes_facing_out() {
+        let gltf = Gltf::load("./assets/gltf/triangle.glb").unwrap();
+        assert_eq!(gltf.primitives.len(), 2);
+        assert_eq!(gltf.get_mesh().triangle_count(), 2);
+
+        let turned = gltf.primitive("turned").unwrap();
+        assert!((turned.mesh.vertices[1].position - Vec3::new(0.0, 0.0, -1.0)).magnitude() < 1e-6);
+        for vertex in &turned.mesh.vertices {
+            assert!((vertex.normal - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-6);
+            assert_eq!(vertex.color.to_hex(), 0xff0000);
+        }
+        assert!((facing(&turned.mesh) - Vec3::new(1.0, 0.0, 0.0)).magnitude() < 1e-6);
+
+        let mirrored = gltf.primitive("mirrored").unwrap();
+        assert!((mirrored.mesh.vertices[0].normal - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+        assert!((facing(&mirrored.mesh) - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+        assert_eq!(mirrored.texture.texel(0, 0, 0).to_hex(), 0xff0000);
+
+        assert_eq!(gltf.scene_node("triangles").children.len(), 2);
+        assert!(matches!(Gltf::load("./assets/gltf/missing.glb"), Err(GltfError::Import(..))));
+    }
+}
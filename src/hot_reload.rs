@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::planet_definition::PlanetDefinition;
+
+// Files are checked at most this often
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Watches a directory of planet description files by polling modification
+// times. A file whose time or size changed is parsed again; files that fail
+// keep their error until they are fixed or removed.
+pub struct DefinitionWatcher {
+    directory: PathBuf,
+    seen: HashMap<PathBuf, (SystemTime, u64)>,
+    // Planet name of the last definition each file loaded
+    names: HashMap<PathBuf, String>,
+    errors: BTreeMap<PathBuf, String>,
+    last_poll: Option<Instant>,
+}
+
+// What one poll found
+#[derive(Default)]
+pub struct DefinitionChanges {
+    // Definitions from every new or changed file that parsed successfully
+    pub loaded: Vec<PlanetDefinition>,
+    // Planets no file defines any more, because their file was deleted or
+    // now uses another name
+    pub removed: Vec<String>,
+}
+
+impl DefinitionWatcher {
+    // Nothing is loaded until the first poll, which reports every file
+    pub fn new(directory: &Path) -> Self {
+        DefinitionWatcher {
+            directory: directory.to_path_buf(),
+            seen: HashMap::new(),
+            names: HashMap::new(),
+            errors: BTreeMap::new(),
+            last_poll: None,
+        }
+    }
+
+    // Like poll_now, but returns nothing if the last poll was too recent
+    pub fn poll(&mut self) -> DefinitionChanges {
+        if self.last_poll.is_some_and(|last| last.elapsed() < POLL_INTERVAL) {
+            return DefinitionChanges::default();
+        }
+        self.poll_now()
+    }
+
+    pub fn poll_now(&mut self) -> DefinitionChanges {
+        self.last_poll = Some(Instant::now());
+
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(error) => {
+                self.errors.insert(self.directory.clone(), format!("{}: {}", self.directory.display(), error));
+                return DefinitionChanges::default();
+            }
+        };
+        self.errors.remove(&self.directory);
+
+        let mut present = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "toml") {
+                if let Ok(metadata) = entry.metadata() {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    present.push((path, (modified, metadata.len())));
+                }
+            }
+        }
+        present.sort();
+
+        // Removed files take their errors and planets with them
+        let mut changes = DefinitionChanges::default();
+        self.seen.retain(|path, _| present.iter().any(|(p, _)| p == path));
+        self.errors.retain(|path, _| *path == self.directory || present.iter().any(|(p, _)| p == path));
+        let removed: Vec<PathBuf> = self.names.keys()
+            .filter(|path| !present.iter().any(|(p, _)| p == *path))
+            .cloned()
+            .collect();
+        for path in removed {
+            changes.removed.extend(self.names.remove(&path));
+        }
+
+        for (path, stamp) in present {
+            if self.seen.get(&path) == Some(&stamp) {
+                continue;
+            }
+            self.seen.insert(path.clone(), stamp);
+            match PlanetDefinition::load(&path) {
+                Ok(definition) => {
+                    self.errors.remove(&path);
+                    if let Some(old) = self.names.insert(path, definition.name.clone()) {
+                        if old != definition.name {
+                            changes.removed.push(old);
+                        }
+                    }
+                    changes.loaded.push(definition);
+                }
+                Err(error) => {
+                    self.errors.insert(path, error.to_string());
+                }
+            }
+        }
+        // A name another file still defines stays registered
+        changes.removed.retain(|name| !self.names.values().any(|defined| defined == name));
+        changes
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &str> {
+        self.errors.values().map(|error| error.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "name = \"test\"\n[[layers]]\nramp = [{ at = 0.0, color = \"#ff0000\" }]\n";
+
+    #[test]
+    fn reports_changes_and_errors() {
+        let directory = std::env::temp_dir().join(format!("planet_watch_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("test.toml");
+
+        fs::write(&file, VALID).unwrap();
+        let mut watcher = DefinitionWatcher::new(&directory);
+        assert_eq!(watcher.poll_now().loaded.len(), 1);
+        assert!(watcher.poll_now().loaded.is_empty());
+
+        // Broken file: nothing loads and the error is kept
+        fs::write(&file, "name = ").unwrap();
+        let changes = watcher.poll_now();
+        assert!(changes.loaded.is_empty() && changes.removed.is_empty());
+        assert_eq!(watcher.errors().count(), 1);
+
+        // Fixed again, under another name
+        fs::write(&file, VALID.replace("test", "fixed")).unwrap();
+        let changes = watcher.poll_now();
+        assert_eq!(changes.loaded[0].name, "fixed");
+        assert_eq!(changes.removed, ["test"]);
+        assert_eq!(watcher.errors().count(), 0);
+
+        fs::write(&file, "broken").unwrap();
+        watcher.poll_now();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(watcher.poll_now().removed, ["fixed"]);
+        assert_eq!(watcher.errors().count(), 0);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,30 @@
+pub mod bake;
+pub mod bounding_box;
+pub mod camera;
+pub mod clipping;
+pub mod displacement;
+pub mod export;
+pub mod fragments;
+pub mod gltf;
+pub mod hot_reload;
+pub mod lod;
+pub mod matrix;
+pub mod mesh;
+pub mod obj;
+pub mod orbit;
+pub mod offscreen;
+pub mod planet_definition;
+pub mod planet_graphs;
+pub mod planet_noise;
+pub mod primitives;
+pub mod registry;
+pub mod scene;
+pub mod screen;
+pub mod shader;
+pub mod shader_graph;
+pub mod solar_system;
+pub mod texture;
+pub mod tiles;
+pub mod turntable;
+pub mod uniforms;
+pub mod vertex;
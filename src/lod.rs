@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+use crate::mesh::Mesh;
+use crate::primitives::{annulus, uv_sphere};
+use crate::scene::SceneNode;
+use crate::uniforms::Uniforms;
+
+// A level is only left once the screen radius is this fraction past its
+// threshold, so a body hovering at a threshold doesn't flicker between two
+// levels
+pub const HYSTERESIS: f32 = 0.2;
+
+pub struct LodLevel {
+    pub mesh: Arc<Mesh>,
+    // Smallest projected radius, in pixels, this level is meant for
+    pub min_radius: f32,
+}
+
+// The same object at several tessellations, finest first
+pub struct LodMesh {
+    levels: Vec<LodLevel>,
+    bounding_radius: f32,
+}
+
+impl LodMesh {
+    // Levels may come in any order; the coarsest one is used down to a
+    // radius of zero
+    pub fn new(mut levels: Vec<LodLevel>) -> Self {
+        assert!(!levels.is_empty(), "a LOD mesh needs at least one level");
+        levels.sort_by(|a, b| b.min_radius.total_cmp(&a.min_radius));
+        if let Some(coarsest) = levels.last_mut() {
+            coarsest.min_radius = 0.0;
+        }
+        let bounding_radius = levels.iter()
+            .flat_map(|level| level.mesh.vertices.iter())
+            .map(|vertex| vertex.position.magnitude())
+            .fold(0.0, f32::max);
+        LodMesh { levels, bounding_radius }
+    }
+
+    // `full` close up, then generated spheres of `radius` with fewer
+    // triangles; with `ring`, each of those gets a flat ring between the
+    // given inner and outer radius, matching the one in `full`
+    pub fn planet(full: Arc<Mesh>, radius: f32, ring: Option<(f32, f32)>) -> Self {
+        let coarse = |segments: usize, min_radius: f32| {
+            let mut mesh = uv_sphere(radius, segments, segments / 2);
+            if let Some((inner, outer)) = ring {
+                mesh.extend(annulus(inner, outer, segments * 2));
+            }
+            LodLevel { mesh: Arc::new(Mesh::from_triangles(&mesh)), min_radius }
+        };
+        LodMesh::new(vec![
+            LodLevel { mesh: full, min_radius: 40.0 },
+            coarse(16, 10.0),
+            coarse(8, 0.0),
+        ])
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn level(&self, index: usize) -> &LodLevel {
+        &self.levels[index.min(self.levels.len() - 1)]
+    }
+
+    // Distance from the object's origin to its farthest vertex
+    pub fn bounding_radius(&self) -> f32 {
+        self.bounding_radius
+    }
+
+    // The level to draw at `screen_radius` pixels, moving at most as far
+    // from `current` as the hysteresis band allows
+    pub fn select(&self, current: usize, screen_radius: f32) -> usize {
+        let mut level = current.min(self.levels.len() - 1);
+        while level > 0 && screen_radius >= self.levels[level - 1].min_radius * (1.0 + HYSTERESIS) {
+            level -= 1;
+        }
+        while level + 1 < self.levels.len() && screen_radius < self.levels[level].min_radius * (1.0 - HYSTERESIS) {
+            level += 1;
+        }
+        level
+    }
+}
+
+// Radius in pixels of a sphere of `radius` at world position `center` as
+// seen through the frame's camera, or 0 if it is behind the camera
+pub fn projected_radius(center: Vec3, radius: f32, frame: &Uniforms) -> f32 {
+    let view_center = frame.view_matrix * Vec4::new(center.x, center.y, center.z, 1.0);
+    let to_screen = |point: Vec4| {
+        let clip = frame.projection_matrix * point;
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let screen = frame.viewport_matrix * (clip / clip.w);
+        Some(Vec3::new(screen.x, screen.y, 0.0))
+    };
+
+    // Offsets across the view direction, so the result doesn't depend on the
+    // sphere's orientation; the larger axis wins if the projection is uneven
+    let Some(middle) = to_screen(view_center) else { return 0.0 };
+    [Vec4::new(radius, 0.0, 0.0, 0.0), Vec4::new(0.0, radius, 0.0, 0.0)].iter()
+        .filter_map(|offset| to_screen(view_center + offset))
+        .map(|edge| (edge - middle).magnitude())
+        .fold(0.0, f32::max)
+}
+
+// Picks every LOD node's mesh for the frame's camera. Run it before
+// render_scene each frame.
+pub fn update_lods(root: &mut SceneNode, frame: &Uniforms) {
+    update_node(root, frame, Mat4::identity());
+}
+
+fn update_node(node: &mut SceneNode, frame: &Uniforms, parent: Mat4) {
+    let world = parent * node.transform.matrix();
+
+    if let Some(lods) = &node.lods {
+        let origin = world * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let scale = (0..3).map(|column| world.column(column).xyz().magnitude()).fold(0.0, f32::max);
+        let screen_radius = projected_radius(origin.xyz(), lods.bounding_radius() * scale, frame);
+        node.lod_level = lods.select(node.lod_level, screen_radius);
+        node.mesh = Some(lods.level(node.lod_level).mesh.clone());
+    }
+
+    for child in &mut node.children {
+        update_node(child, frame, world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastnoise_lite::FastNoiseLite;
+    use crate::matrix::{create_perspective_matrix, create_view_matrix, create_viewport_matrix};
+    use crate::registry::ShaderRegistry;
+    use crate::uniforms::{CullMode, Winding};
+
+    fn levels() -> LodMesh {
+        let mesh = |segments| Arc::new(Mesh::from_triangles(&uv_sphere(1.0, segments, segments / 2)));
+        LodMesh::new(vec![
+            LodLevel { mesh: mesh(4), min_radius: 5.0 },
+            LodLevel { mesh: mesh(16), min_radius: 100.0 },
+            LodLevel { mesh: mesh(8), min_radius: 30.0 },
+        ])
+    }
+
+    fn camera_at(distance: f32) -> Uniforms {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: create_view_matrix(Vec3::new(0.0, 0.0, -distance), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0)),
+            projection_matrix: create_perspective_matrix(800.0, 600.0),
+            viewport_matrix: create_viewport_matrix(800.0, 600.0),
+            light_dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0,
+            noise: FastNoiseLite::new(),
+            cull_mode: CullMode::Back,
+            front_face: Winding::CounterClockwise,
+        }
+    }
+
+    #[test]
+    fn selection_switches_past_the_hysteresis_band() {
+        let lods = levels();
+        assert_eq!(lods.level(0).mesh.triangle_count(), uv_sphere(1.0, 16, 8).len() / 3);
+        assert_eq!(lods.level(2).min_radius, 0.0);
+
+        assert_eq!(lods.select(2, 500.0), 0);
+        assert_eq!(lods.select(0, 1.0), 2);
+        // Just under the threshold keeps the finer level, just over keeps the coarser one
+        assert_eq!(lods.select(0, 95.0), 0);
+        assert_eq!(lods.select(1, 105.0), 1);
+        assert_eq!(lods.select(0, 75.0), 1);
+        assert_eq!(lods.select(1, 125.0), 0);
+    }
+
+    #[test]
+    fn farther_spheres_project_smaller() {
+        let near = projected_radius(Vec3::zeros(), 1.0, &camera_at(5.0));
+        let far = projected_radius(Vec3::zeros(), 1.0, &camera_at(50.0));
+        assert!(near > 0.0 && far > 0.0);
+        assert!((near / far - 10.0).abs() < 0.5, "near {} far {}", near, far);
+        assert_eq!(projected_radius(Vec3::new(0.0, 0.0, -20.0), 1.0, &camera_at(5.0)), 0.0);
+    }
+
+    #[test]
+    fn distant_planets_use_fewer_triangles() {
+        let mut node = SceneNode::new("planet")
+            .with_lods(Arc::new(levels()), ShaderRegistry::with_planets().get("earth").unwrap().clone());
+        update_lods(&mut node, &camera_at(3.0));
+        assert_eq!(node.lod_level, 0);
+        update_lods(&mut node, &camera_at(400.0));
+        assert_eq!(node.lod_level, 2);
+        assert_eq!(node.mesh.as_ref().unwrap().triangle_count(), levels().level(2).mesh.triangle_count());
+    }
+}
@@ -1,23 +1,22 @@
-use std::time::Duration;
-use minifb::{Key, Window, WindowOptions};
-use nalgebra_glm::{look_at, perspective, Mat4, Vec1, Vec3};
-use screen::framebuffer;
-use obj::Obj;
-use uniforms::Uniforms;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use nalgebra_glm::{Mat4, Vec3};
 use std::f32::consts::PI;
-use camera::Camera;
-use rand;
-use fastnoise_lite::{self, CellularDistanceFunction, DomainWarpType, FastNoiseLite, FractalType, NoiseType};
-
-mod screen;
-mod vertex;
-mod fragments;
-mod obj;
-mod uniforms;
-mod shader;
-mod bounding_box;
-mod camera;
-mod planet_noise;
+use cg_custom_shaders::camera::Camera;
+use cg_custom_shaders::hot_reload::DefinitionWatcher;
+use cg_custom_shaders::lod::update_lods;
+use cg_custom_shaders::matrix::{create_model_matrix, create_perspective_matrix, create_view_matrix, create_viewport_matrix};
+use cg_custom_shaders::obj::PlanetMeshes;
+use cg_custom_shaders::orbit::SimulationClock;
+use cg_custom_shaders::registry::{ShaderProgram, ShaderRegistry};
+use cg_custom_shaders::scene::render_scene;
+use cg_custom_shaders::screen::color::Color;
+use cg_custom_shaders::screen::framebuffer;
+use cg_custom_shaders::screen::text::draw_message_box;
+use cg_custom_shaders::solar_system::{body_rotation, build_solar_system, update_bodies, update_programs, SUN_LIGHT};
+use cg_custom_shaders::uniforms::{self, CullMode, Uniforms, Winding};
 
 fn main() {
     // Window
@@ -40,20 +39,11 @@ fn main() {
     let frame_delay = Duration::from_millis(16);
 
     // Obj
-    // Normal Planet
-    let planet =  Obj::load("./assets/3d_models/sphere.obj").expect("Failed to load obj");
-    let vertex_array = planet.get_vertex_array();
-    // Planet with Ring
-    let planet_ring = Obj::load("./assets/3d_models/sphere_ring.obj").expect("Failed to load obj");
-    let vertex_array_ring = planet_ring.get_vertex_array();
-    // Planet with moon
-    let planet_moon = Obj::load("./assets/3d_models/sphere_moon.obj").expect("Failed to load obj");
-    let vertex_array_moon = planet_moon.get_vertex_array();
+    let meshes = PlanetMeshes::load("./assets/3d_models").expect("Failed to load obj");
     let light_dir= Vec3::new(1.0, 3.0, -4.0);
 
     // Model
     let translation = Vec3::new(0.0, 0.0, 0.0);
-    let rotation = Vec3::new(0.0, 0.0, 0.0);
     let scale = 1.0f32;
 
     // Camera
@@ -63,8 +53,25 @@ fn main() {
         Vec3::new(0.0, 1.0, 0.0)
     );
 
+    // Camera used while the whole solar system is shown
+    let mut system_camera = Camera::new(
+        Vec3::new(9.0, 14.0, -22.0),
+        Vec3::new(9.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0)
+    );
+
     let mut frame_counter = 0;
-    let noise = planet_noise::get_earth_noise();
+    let mut graphs = false;
+    let mut registry = built_in_planets(graphs);
+    // What a planet goes back to when the file that replaced it is deleted
+    let mut built_in = registry.clone();
+    let mut watcher = DefinitionWatcher::new(Path::new("./assets/planets"));
+    let mut solar_system = build_solar_system(&registry, &meshes).expect("every body has a shader");
+    let mut show_system = false;
+    let mut clock = SimulationClock::default();
+    let mut last_frame = Instant::now();
+    let mut program = registry.get("earth").expect("earth shader is registered").clone();
+    let noise = program.create_noise();
     let projection_matrix = create_perspective_matrix(window_width as f32, window_height as f32);
         let viewport_matrix = create_viewport_matrix(framebuffer_width as f32, framebuffer_height as f32);
     let mut uniforms = Uniforms { 
@@ -75,7 +82,8 @@ fn main() {
       light_dir, 
       time: 0, 
       noise,
-      planet: 4
+      cull_mode: CullMode::Back,
+      front_face: Winding::CounterClockwise,
     };
     // Main Window Loop:
     while window.is_open() {
@@ -86,21 +94,58 @@ fn main() {
         }
 
         // Input listener
-        handle_input(&window, &mut camera);
-        uniforms.model_matrix = create_model_matrix(translation, scale, rotation);
-        uniforms.view_matrix = create_view_matrix(camera.eye, camera.center, camera.up);
+        let active_camera = if show_system { &mut system_camera } else { &mut camera };
+        handle_input(&window, active_camera);
+        uniforms.view_matrix = create_view_matrix(active_camera.eye, active_camera.center, active_camera.up);
         uniforms.time = frame_counter;
 
-        change_planet(&window, &mut uniforms);
+        if change_planet(&window, &registry, &mut program, &mut uniforms) {
+          show_system = false;
+        }
+        if window.is_key_pressed(Key::Key0, KeyRepeat::No) {
+          show_system = !show_system;
+        }
+        change_cull_mode(&window, &mut uniforms);
+        change_time_scale(&window, &mut clock);
+        let switched = toggle_graphs(&window, &mut graphs, &mut registry, &mut built_in, &mut program, &mut uniforms);
+        let reloaded = reload_definitions(&mut watcher, &mut registry, &built_in, &mut program, &mut uniforms);
+        if switched || reloaded {
+          update_programs(&mut solar_system, &registry);
+        }
+
+        // Simulation stage
+        let now = Instant::now();
+        clock.advance(now.duration_since(last_frame).as_secs_f64());
+        last_frame = now;
+        update_bodies(&mut solar_system, clock.days);
+        let rotation = body_rotation(&program.name, clock.days).unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+        uniforms.model_matrix = create_model_matrix(translation, scale, rotation);
+
         // Rendering stage
-        if uniforms.planet == 6{
-          uniforms::render(&mut framebuffer, &uniforms, &vertex_array_ring);
-        }else if uniforms.planet == 4{
-          uniforms::render(&mut framebuffer, &uniforms, &vertex_array_moon);
-        } else{
-          uniforms::render(&mut framebuffer, &uniforms, &vertex_array);
+        let (stats, showing) = if show_system {
+          update_lods(&mut solar_system, &uniforms);
+          (render_scene(&mut framebuffer, &solar_system, &uniforms, SUN_LIGHT), "solar system")
+        } else {
+          // A mesh that needs its own cull mode (Saturn's one-sided ring) overrides the C key while drawn
+          let cull_mode = uniforms.cull_mode;
+          uniforms.cull_mode = meshes.cull_mode(&program.name).unwrap_or(cull_mode);
+          let stats = uniforms::render(&mut framebuffer, &uniforms, meshes.for_planet(&program.name), &program);
+          uniforms.cull_mode = cull_mode;
+          (stats, program.name.as_str())
+        };
+        let speed = if clock.paused { "paused".to_string() } else { format!("{}x", clock.rate) };
+        window.set_title(&format!(
+          "3D modeling - Render Pipeline | {} | day {:.0} ({}) | cull: {:?} | shaders: {} | {} triangles, {} culled, {} discarded",
+          showing, clock.days, speed, uniforms.cull_mode, if graphs { "graphs" } else { "code" },
+          stats.triangles, stats.culled, stats.discarded
+        ));
+
+        // Errors from planet files are drawn over the frame until fixed
+        let mut message_y = 4;
+        for error in watcher.errors() {
+          message_y += draw_message_box(&mut framebuffer, 4, message_y, framebuffer_width - 8, error, Color::new(255, 120, 120)) + 4;
         }
-      
+
         frame_counter+=1;
         window
             .update_with_buffer(
@@ -114,97 +159,133 @@ fn main() {
 }
 
 
-fn create_model_matrix(
-    translation: Vec3,
-    scale: f32,
-    rotation: Vec3
-)
-->Mat4 {
-    let (sin_x, cos_x) = rotation.x.sin_cos();
-    let (sin_y, cos_y) = rotation.y.sin_cos();
-    let (sin_z, cos_z) = rotation.z.sin_cos();
-    
-    let rotation_matrix_x = Mat4::new(
-        1.0,  0.0,    0.0,   0.0,
-        0.0,  cos_x, -sin_x, 0.0,
-        0.0,  sin_x,  cos_x, 0.0,
-        0.0,  0.0,    0.0,   1.0,
-    );
-    let rotation_matrix_y = Mat4::new(
-        cos_y,  0.0,  sin_y, 0.0,
-        0.0,    1.0,  0.0,   0.0,
-        -sin_y, 0.0,  cos_y, 0.0,
-        0.0,    0.0,  0.0,   1.0,
-    );
-    let rotation_matrix_z = Mat4::new(
-        cos_z, -sin_z, 0.0, 0.0,
-        sin_z,  cos_z, 0.0, 0.0,
-        0.0,    0.0,  1.0, 0.0,
-        0.0,    0.0,  0.0, 1.0,
-    );
-
-    let rotation_matrix = rotation_matrix_z * rotation_matrix_y * rotation_matrix_x;
-
-    let transform_matrix = Mat4::new(
-        scale, 0.0,   0.0,   translation.x,
-        0.0,   scale, 0.0,   translation.y,
-        0.0,   0.0,   scale, translation.z,
-        0.0,   0.0,   0.0,   1.0,
-    );
-
-    transform_matrix * rotation_matrix
+// The sun, planets and moon written in Rust or as node graphs, plus the
+// textured planets, with the baked maps swapped in
+fn built_in_planets(graphs: bool) -> ShaderRegistry {
+    let mut registry = if graphs { ShaderRegistry::with_planet_graphs() } else { ShaderRegistry::with_planets() };
+    let textures = Path::new("./assets/textures");
+    if textures.is_dir() {
+        if let Err(error) = registry.register_textures(textures) {
+            eprintln!("warning: {}", error);
+        }
+    }
+    // Maps written by the bake tool replace the shaders they were baked from
+    let baked = Path::new("./assets/baked");
+    if baked.is_dir() {
+        if let Err(error) = registry.use_baked_maps(baked) {
+            eprintln!("warning: {}", error);
+        }
+    }
+    registry
 }
 
-fn create_view_matrix(eye: Vec3, center: Vec3, up: Vec3) -> Mat4 {
-    look_at(&eye, &center, &up)
+// G switches the built-in planets between their hand-written shaders and
+// node graphs; planets replaced by a planet file keep the file's look
+fn toggle_graphs(window: &Window, graphs: &mut bool, registry: &mut ShaderRegistry, built_in: &mut ShaderRegistry, program: &mut ShaderProgram, uniforms: &mut Uniforms) -> bool{
+  if !window.is_key_pressed(Key::G, KeyRepeat::No) {
+    return false;
+  }
+  *graphs = !*graphs;
+  let swapped = built_in_planets(*graphs);
+  for name in swapped.names() {
+    let unchanged = match (registry.get(name), built_in.get(name)) {
+      (Some(current), Some(original)) => Arc::ptr_eq(&current.fragment, &original.fragment),
+      _ => false,
+    };
+    if unchanged {
+      registry.register(swapped.get(name).expect("name comes from this registry").clone());
+    }
+  }
+  *built_in = swapped;
+  if let Some(updated) = registry.get(&program.name) {
+    *program = updated.clone();
+    uniforms.noise = program.create_noise();
+  }
+  true
 }
 
-fn create_perspective_matrix(window_width: f32, window_height: f32) -> Mat4 {
-    let fov = 45.0 * PI / 180.0;
-    let aspect_ratio = window_width / window_height;
-    let near = 0.1;
-    let far = 1000.0;
+// Number keys pick shader programs in registry order, N steps to the next
+// one (reaching those past the ninth) and 0 toggles the solar system
+const PLANET_KEYS: [Key; 9] = [
+  Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5,
+  Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+];
 
-    perspective(fov, aspect_ratio, near, far)
-}
+fn change_planet(window: &Window, registry: &ShaderRegistry, program: &mut ShaderProgram, uniforms: &mut Uniforms) -> bool{
+  let mut selected = None;
+  for (index, key) in PLANET_KEYS.iter().enumerate() {
+    if window.is_key_down(*key) {
+      selected = registry.get_index(index);
+    }
+  }
+  if window.is_key_pressed(Key::N, KeyRepeat::No) {
+    let current = registry.names().position(|name| name == program.name).unwrap_or(0);
+    selected = registry.get_index((current + 1) % registry.len());
+  }
 
-fn create_viewport_matrix(width: f32, height: f32) -> Mat4 {
-    Mat4::new(
-        width / 2.0, 0.0, 0.0, width / 2.0,
-        0.0, -height / 2.0, 0.0, height / 2.0,
-        0.0, 0.0, 1.0, 0.0,
-        0.0, 0.0, 0.0, 1.0
-    )
+  match selected {
+    Some(selected) => {
+      *program = selected.clone();
+      uniforms.noise = program.create_noise();
+      true
+    }
+    None => false,
+  }
 }
 
-fn change_planet(window: &Window, uniforms: &mut Uniforms){
-  if window.is_key_down(Key::Key1) {
-    uniforms.planet = 1; // Sun
-    uniforms.noise = planet_noise::get_sun_noise();
+// Registers changed planet files and drops the planets of deleted ones (or
+// restores the built-in planet they replaced), swapping the look on screen
+// if it is one of them. Returns whether anything changed.
+fn reload_definitions(watcher: &mut DefinitionWatcher, registry: &mut ShaderRegistry, built_in: &ShaderRegistry, program: &mut ShaderProgram, uniforms: &mut Uniforms) -> bool{
+  let changes = watcher.poll();
+  if changes.loaded.is_empty() && changes.removed.is_empty() {
+    return false;
+  }
+  let on_screen = changes.loaded.iter().any(|definition| definition.name == program.name)
+    || changes.removed.contains(&program.name);
+  for definition in changes.loaded {
+    registry.register(definition.into_program());
   }
-  if window.is_key_down(Key::Key2) {
-    uniforms.planet = 2; // Mercury
-    uniforms.noise = planet_noise::get_mercury_noise();
+  for name in &changes.removed {
+    match built_in.get(name) {
+      Some(original) => registry.register(original.clone()),
+      None => {
+        registry.remove(name);
+      }
+    }
   }
-  if window.is_key_down(Key::Key3) {
-    uniforms.planet = 3; // Venus
-    uniforms.noise = planet_noise::get_venus_noise();
+  if on_screen {
+    if let Some(updated) = registry.get(&program.name).or_else(|| registry.get_index(0)) {
+      *program = updated.clone();
+      uniforms.noise = program.create_noise();
+    }
   }
-  if window.is_key_down(Key::Key4) {
-    uniforms.planet = 4; // Earth
-    uniforms.noise = planet_noise::get_earth_noise();
+  true
+}
+
+fn change_cull_mode(window: &Window, uniforms: &mut Uniforms){
+  if window.is_key_pressed(Key::C, KeyRepeat::No) {
+    uniforms.cull_mode = match uniforms.cull_mode {
+      CullMode::Back => CullMode::Front,
+      CullMode::Front => CullMode::None,
+      CullMode::None => CullMode::Back,
+    };
+  }
+}
+
+// Space pauses, T runs at 1x (a day per second), Y at 1000x and R reverses time
+fn change_time_scale(window: &Window, clock: &mut SimulationClock){
+  if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+    clock.toggle_pause();
   }
-  if window.is_key_down(Key::Key5) {
-    uniforms.planet = 5; // Jupiter
-    uniforms.noise = planet_noise::get_jupiter_noise();
+  if window.is_key_pressed(Key::T, KeyRepeat::No) {
+    clock.set_speed(1.0);
   }
-  if window.is_key_down(Key::Key6) {
-    uniforms.planet = 6; // Saturn
-    uniforms.noise = planet_noise::get_saturn_noise();
+  if window.is_key_pressed(Key::Y, KeyRepeat::No) {
+    clock.set_speed(1000.0);
   }
-  if window.is_key_down(Key::Key7) {
-    uniforms.planet = 7; // Neptune
-    uniforms.noise = planet_noise::get_neptune_noise();
+  if window.is_key_pressed(Key::R, KeyRepeat::No) {
+    clock.reverse();
   }
 }
 
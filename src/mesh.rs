@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+use crate::vertex::Vertex;
+
+// Unique vertices plus three indices per triangle, wound counter-clockwise
+// seen from the front. The renderer runs the vertex shader once per vertex,
+// however many triangles share it.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        assert!(indices.len().is_multiple_of(3), "indices must come in threes");
+        assert!(indices.iter().all(|&index| (index as usize) < vertices.len()), "index past the last vertex");
+        Mesh { vertices, indices }
+    }
+
+    // Indexes a triangle list such as the ones primitives.rs returns. Only
+    // vertices with exactly the same attributes are merged, so the mesh
+    // renders exactly like the list did.
+    pub fn from_triangles(triangles: &[Vertex]) -> Self {
+        let mut unique: HashMap<([u32; 8], u32), u32> = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len() - triangles.len() % 3);
+        for vertex in &triangles[..triangles.len() - triangles.len() % 3] {
+            let (p, n, t) = (vertex.position, vertex.normal, vertex.tex_coords);
+            let key = (
+                [p.x, p.y, p.z, n.x, n.y, n.z, t.x, t.y].map(f32::to_bits),
+                vertex.color.to_hex(),
+            );
+            let index = *unique.entry(key).or_insert_with(|| {
+                vertices.push(vertex.clone());
+                vertices.len() as u32 - 1
+            });
+            indices.push(index);
+        }
+        Mesh { vertices, indices }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [&Vertex; 3]> {
+        self.indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|i| &self.vertices[triangle[i] as usize]))
+    }
+
+    // Back to one vertex per index, three per triangle
+    pub fn to_triangles(&self) -> Vec<Vertex> {
+        self.indices.iter().map(|&index| self.vertices[index as usize].clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use fastnoise_lite::FastNoiseLite;
+    use nalgebra_glm::{Mat4, Vec3};
+    use crate::matrix::{create_perspective_matrix, create_view_matrix, create_viewport_matrix};
+    use crate::primitives::uv_sphere;
+    use crate::registry::ShaderRegistry;
+    use crate::screen::framebuffer::Framebuffer;
+    use crate::shader::vertex_shader;
+    use crate::uniforms::{render_with_threads, CullMode, Uniforms, Winding};
+
+    #[test]
+    fn indexing_a_triangle_list_shares_vertices() {
+        let triangles = uv_sphere(0.5, 16, 8);
+        let mesh = Mesh::from_triangles(&triangles);
+        assert_eq!(mesh.triangle_count(), triangles.len() / 3);
+        // One vertex per grid point, with a duplicate column on the seam and
+        // one vertex per segment at each pole
+        assert_eq!(mesh.vertices.len(), 17 * 7 + 16 * 2);
+
+        let back = mesh.to_triangles();
+        for (original, indexed) in triangles.iter().zip(&back) {
+            assert_eq!(original.position, indexed.position);
+            assert_eq!(original.tex_coords, indexed.tex_coords);
+        }
+    }
+
+    #[test]
+    fn each_vertex_is_shaded_once() {
+        let mesh = Mesh::from_triangles(&uv_sphere(0.5, 16, 8));
+        let shaded = Arc::new(AtomicUsize::new(0));
+        let counter = shaded.clone();
+        let program = ShaderRegistry::with_planets().get("earth").unwrap().clone()
+            .with_vertex_shader(move |vertex: &Vertex, uniforms: &Uniforms| {
+                counter.fetch_add(1, Ordering::Relaxed);
+                vertex_shader(vertex, uniforms)
+            });
+        let uniforms = Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: create_view_matrix(Vec3::new(0.0, 0.0, -3.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0)),
+            projection_matrix: create_perspective_matrix(64.0, 64.0),
+            viewport_matrix: create_viewport_matrix(64.0, 64.0),
+            light_dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0,
+            noise: FastNoiseLite::new(),
+            cull_mode: CullMode::Back,
+            front_face: Winding::CounterClockwise,
+        };
+
+        let stats = render_with_threads(&mut Framebuffer::new(64, 64), &uniforms, &mesh, &program, 1);
+        assert_eq!(shaded.load(Ordering::Relaxed), mesh.vertices.len());
+        assert_eq!(stats.triangles, mesh.triangle_count());
+        assert!(stats.rasterized > 0);
+    }
+}
@@ -1,23 +1,137 @@
-use tobj;
-use nalgebra_glm::{Vec2, Vec3};
+use std::collections::HashMap;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use nalgebra_glm::{Vec2, Vec3, Vec4};
+use crate::mesh::Mesh;
+use crate::primitives::{annulus, double_sided, translated, uv_sphere};
+use crate::uniforms::CullMode;
 use crate::{screen::color::Color, vertex::Vertex};
 
-pub struct Obj {
+// Vertex color of every loaded or generated mesh without a diffuse material color
+pub const MESH_COLOR: u32 = 0x5797ff;
+
+// Radius of the bundled sphere models, and the inner and outer radius of
+// the flat ring in sphere_ring.obj
+pub const SPHERE_RADIUS: f32 = 0.5;
+pub const RING_INNER_RADIUS: f32 = 0.94;
+pub const RING_OUTER_RADIUS: f32 = 0.965;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Load(String, tobj::LoadError),
+    NoModels(String),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Load(file, error) => write!(f, "{}: {}", file, error),
+            ObjError::NoModels(file) => write!(f, "{}: no models", file),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Load(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// One `o`/`g` object of an OBJ file. Normals and texture coordinates always
+// line up with the positions: missing ones are generated on load.
+pub struct ObjModel {
+    pub name: String,
     pub vertices: Vec<Vec3>,
     pub normals: Vec<Vec3>,
     pub texcoords: Vec<Vec2>,
     pub indices: Vec<u32>,
+    // Index into Obj::materials
+    pub material_id: Option<usize>,
+}
+
+pub struct Obj {
+    pub models: Vec<ObjModel>,
+    pub materials: Vec<tobj::Material>,
+    // Why the MTL file couldn't be read, if it couldn't; the models still load
+    pub material_error: Option<String>,
 }
 
 impl Obj {
-    pub fn load(filename: &str) -> Result<Self, tobj::LoadError> {
-        let (models, _) = tobj::load_obj(filename, &tobj::LoadOptions {
+    pub fn load(filename: &str) -> Result<Self, ObjError> {
+        let (models, materials) = tobj::load_obj(filename, &tobj::LoadOptions {
             single_index: true,
             triangulate: true,
             ..Default::default()
-        })?;
+        }).map_err(|error| ObjError::Load(filename.to_string(), error))?;
+        if models.is_empty() {
+            return Err(ObjError::NoModels(filename.to_string()));
+        }
+        let (materials, material_error) = match materials {
+            Ok(materials) => (materials, None),
+            Err(error) => (Vec::new(), Some(error.to_string())),
+        };
 
-        let mesh = &models[0].mesh;
+        // tobj already rejects faces that refer to missing vertices
+        let models = models.into_iter().map(ObjModel::from_tobj).collect();
+        Ok(Obj { models, materials, material_error })
+    }
+
+    pub fn model(&self, name: &str) -> Option<&ObjModel> {
+        self.models.iter().find(|model| model.name == name)
+    }
+
+    pub fn material(&self, model: &ObjModel) -> Option<&tobj::Material> {
+        model.material_id.and_then(|id| self.materials.get(id))
+    }
+
+    // Every model in one indexed mesh, sharing vertices the way the file does
+    pub fn get_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::default();
+        for model in &self.models {
+            let model_mesh = self.get_model_mesh(model);
+            let offset = mesh.vertices.len() as u32;
+            mesh.indices.extend(model_mesh.indices.iter().map(|index| index + offset));
+            mesh.vertices.extend(model_mesh.vertices);
+        }
+        mesh
+    }
+
+    // Vertices are colored with their material's diffuse color
+    pub fn get_model_mesh(&self, model: &ObjModel) -> Mesh {
+        let vertex_color = match self.material(model).and_then(|material| material.diffuse) {
+            Some([r, g, b]) => Color::new((r * 255.0) as i32, (g * 255.0) as i32, (b * 255.0) as i32),
+            None => Color::from_hex(MESH_COLOR),
+        };
+        let vertices = (0..model.vertices.len())
+            .map(|i| Vertex{
+                color: vertex_color,
+                position: model.vertices[i],
+                normal: model.normals[i],
+                tex_coords: model.texcoords[i],
+                clip_position: Vec4::new(0.0,0.0,0.0,1.0),
+                transformed_normal: Vec3::new(0.0,0.0,0.0),
+                transformed_position: Vec3::new(0.0,0.0,0.0),
+                inv_w: 1.0,
+            })
+            .collect();
+        Mesh { vertices, indices: model.indices.clone() }
+    }
+
+    // Every model as one triangle list, three vertices per triangle
+    pub fn get_vertex_array(&self)-> Vec<Vertex>{
+        self.get_mesh().to_triangles()
+    }
+}
+
+impl ObjModel {
+    fn from_tobj(model: tobj::Model) -> Self {
+        let mesh = model.mesh;
 
         let vertices: Vec<Vec3> = mesh.positions.chunks(3)
             .map(|v| Vec3::new(v[0], v[1], v[2]))
@@ -26,36 +140,254 @@ impl Obj {
         let normals: Vec<Vec3> = mesh.normals.chunks(3)
             .map(|n| Vec3::new(n[0], n[1], n[2]))
             .collect();
+        let normals = if normals.len() == vertices.len() {
+            normals
+        } else {
+            smooth_normals(&vertices, &mesh.indices)
+        };
 
         let texcoords: Vec<Vec2> = mesh.texcoords.chunks(2)
             .map(|t| Vec2::new(t[0], t[1]))
             .collect();
+        let texcoords = if texcoords.len() == vertices.len() {
+            texcoords
+        } else {
+            placeholder_tex_coords(&vertices)
+        };
 
-        let indices = mesh.indices.clone();
-
-        Ok(Obj {
+        ObjModel {
+            name: model.name,
             vertices,
             normals,
             texcoords,
-            indices,
+            indices: mesh.indices,
+            material_id: mesh.material_id,
+        }
+    }
+}
+
+// Area-weighted average of the face normals around each position. Vertices
+// at the same position share a normal even if they are separate entries
+// (as they are when their texture coordinates differ).
+pub(crate) fn smooth_normals(vertices: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let key = |position: &Vec3| [position.x.to_bits(), position.y.to_bits(), position.z.to_bits()];
+    let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+        // The cross product's length is twice the area, which does the weighting
+        let face = (b - a).cross(&(c - a));
+        for position in [a, b, c] {
+            *sums.entry(key(&position)).or_insert_with(Vec3::zeros) += face;
+        }
+    }
+    vertices.iter()
+        .map(|position| {
+            let sum = sums.get(&key(position)).copied().unwrap_or_else(Vec3::zeros);
+            if sum.magnitude() > 0.0 { sum.normalize() } else { Vec3::new(0.0, 1.0, 0.0) }
+        })
+        .collect()
+}
+
+// Spherical mapping around the center of the model's bounding box
+pub(crate) fn placeholder_tex_coords(vertices: &[Vec3]) -> Vec<Vec2> {
+    let Some(first) = vertices.first() else { return Vec::new() };
+    let (min, max) = vertices.iter().fold((*first, *first), |(min, max), v| (min.inf(v), max.sup(v)));
+    let center = (min + max) / 2.0;
+    vertices.iter()
+        .map(|position| {
+            let offset = position - center;
+            if offset.magnitude() > 0.0 { spherical_tex_coords(&offset) } else { Vec2::new(0.5, 0.5) }
+        })
+        .collect()
+}
+
+// Equirectangular mapping around the origin: u follows longitude eastward
+// with 0.5 facing -z, v runs from the south pole (0) to the north pole (1)
+pub fn spherical_tex_coords(direction: &Vec3) -> Vec2 {
+    let p = direction.normalize();
+    Vec2::new(0.5 + (-p.x).atan2(-p.z) / (2.0 * PI), 0.5 + p.y.clamp(-1.0, 1.0).asin() / PI)
+}
+
+// The unit vector that spherical_tex_coords maps to `uv`
+pub fn spherical_direction(uv: &Vec2) -> Vec3 {
+    let longitude = (uv.x - 0.5) * 2.0 * PI;
+    let latitude = (uv.y - 0.5) * PI;
+    Vec3::new(-longitude.sin() * latitude.cos(), latitude.sin(), -longitude.cos() * latitude.cos())
+}
+
+// Replaces the texture coordinates of a triangle list with spherical_tex_coords.
+// Triangles crossing the seam get u past 1 so they don't stretch over the
+// whole texture.
+pub fn set_spherical_tex_coords(vertices: &mut [Vertex]) {
+    for triangle in vertices.chunks_mut(3) {
+        let mut coords = [Vec2::zeros(); 3];
+        let mut at_pole = [false; 3];
+        for (i, vertex) in triangle.iter().enumerate() {
+            coords[i] = spherical_tex_coords(&vertex.position);
+            let p = vertex.position.normalize();
+            at_pole[i] = p.x.hypot(p.z) < 1e-4;
+        }
+
+        let (min_u, max_u) = (0..3).filter(|&i| !at_pole[i])
+            .fold((f32::MAX, f32::MIN), |(min, max), i| (min.min(coords[i].x), max.max(coords[i].x)));
+        if max_u - min_u > 0.5 {
+            for coord in coords.iter_mut().filter(|coord| coord.x < 0.5) {
+                coord.x += 1.0;
+            }
+        }
+        // Longitude is undefined at a pole, so it takes the middle of the other two
+        let others: Vec<f32> = (0..3).filter(|&i| !at_pole[i]).map(|i| coords[i].x).collect();
+        if !others.is_empty() {
+            let middle = others.iter().sum::<f32>() / others.len() as f32;
+            for i in (0..3).filter(|&i| at_pole[i]) {
+                coords[i].x = middle;
+            }
+        }
+
+        for (vertex, coord) in triangle.iter_mut().zip(coords) {
+            vertex.tex_coords = coord;
+        }
+    }
+}
+
+// The three bundled planet models: a plain sphere, one with Saturn's ring and one with Earth's moon
+// Shared so scene nodes can hold them without copying
+pub struct PlanetMeshes {
+    pub sphere: Arc<Mesh>,
+    pub ring: Arc<Mesh>,
+    pub moon: Arc<Mesh>,
+    // The ring is one-sided and vanishes from below with back faces culled
+    single_sided_ring: bool,
+}
+
+impl PlanetMeshes {
+    // The models' own texture coordinates only span single faces, so they
+    // are replaced with a spherical mapping for texture-mapped planets. That
+    // mapping is per triangle along the seam, so the mesh is re-indexed after.
+    pub fn load(directory: &str) -> Result<Self, ObjError> {
+        let load = |file: &str| -> Result<Arc<Mesh>, ObjError> {
+            let mut vertices = Obj::load(&format!("{}/{}", directory, file))?.get_vertex_array();
+            set_spherical_tex_coords(&mut vertices);
+            Ok(Arc::new(Mesh::from_triangles(&vertices)))
+        };
+        Ok(PlanetMeshes {
+            sphere: load("sphere.obj")?,
+            ring: load("sphere_ring.obj")?,
+            moon: load("sphere_moon.obj")?,
+            single_sided_ring: true,
         })
     }
 
-    pub fn get_vertex_array(&self)-> Vec<Vertex>{
-        let mut vertex_array = Vec::new();
-        let vertex_color = Color::from_hex(0x5797ff);
-        for i in &self.indices{
-            vertex_array.push(
-                Vertex{
-                    color: vertex_color,
-                    position: self.vertices[*i as usize],
-                    normal: self.normals[*i as usize],
-                    tex_coords: self.texcoords[*i as usize],
-                    transformed_normal: Vec3::new(0.0,0.0,0.0),
-                    transformed_position: Vec3::new(0.0,0.0,0.0),
-                }
-            )
-        }
-        vertex_array
-    }
-}
\ No newline at end of file
+    // The same three models built by the generators in primitives.rs instead
+    // of read from disk, with the ring starting clear of the planet and
+    // seen from both sides
+    pub fn generate() -> Self {
+        let sphere = uv_sphere(SPHERE_RADIUS, 48, 24);
+        let mut ring = sphere.clone();
+        ring.extend(double_sided(&annulus(0.7, 0.96, 96)));
+        let mut moon = sphere.clone();
+        moon.extend(translated(&uv_sphere(0.1, 24, 12), Vec3::new(-0.57, 0.47, -0.77)));
+        PlanetMeshes {
+            sphere: Arc::new(Mesh::from_triangles(&sphere)),
+            ring: Arc::new(Mesh::from_triangles(&ring)),
+            moon: Arc::new(Mesh::from_triangles(&moon)),
+            single_sided_ring: false,
+        }
+    }
+
+    pub fn for_planet(&self, name: &str) -> &Mesh {
+        match name {
+            "saturn" => &self.ring,
+            "earth" => &self.moon,
+            _ => &self.sphere,
+        }
+    }
+
+    // The cull mode a planet's mesh must be drawn with instead of the
+    // viewer's, if it needs one
+    pub fn cull_mode(&self, name: &str) -> Option<CullMode> {
+        match name {
+            "saturn" if self.single_sided_ring => Some(CullMode::None),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spherical_mapping_round_trips() {
+        for uv in [Vec2::new(0.5, 0.5), Vec2::new(0.1, 0.3), Vec2::new(0.9, 0.95), Vec2::new(0.75, 0.05)] {
+            let back = spherical_tex_coords(&spherical_direction(&uv));
+            assert!((back - uv).abs().max() < 1e-5, "{:?} came back as {:?}", uv, back);
+        }
+        assert!((spherical_direction(&Vec2::new(0.5, 0.5)) - Vec3::new(0.0, 0.0, -1.0)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn seam_triangles_do_not_span_the_texture() {
+        let mut triangle = [Vec3::new(-0.1, 0.0, 1.0), Vec3::new(0.1, 0.0, 1.0), Vec3::new(0.0, 0.1, 1.0)]
+            .map(|position| Vertex::new(position, position, Vec2::zeros()));
+        set_spherical_tex_coords(&mut triangle);
+        let us: Vec<f32> = triangle.iter().map(|vertex| vertex.tex_coords.x).collect();
+        let spread = us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(spread < 0.1, "{:?}", us);
+    }
+
+    #[test]
+    fn loads_every_model_and_fills_in_missing_attributes() {
+        let directory = std::env::temp_dir().join(format!("obj-models-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("two.mtl"), "newmtl red\nKd 1.0 0.0 0.0\n").unwrap();
+        std::fs::write(directory.join("two.obj"), "\
+mtllib two.mtl
+o plain
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+f 1 2 3
+f 2 4 3
+o red
+usemtl red
+v 0 0 1
+v 1 0 1
+v 0 1 1
+vt 0 0
+vt 1 0
+vt 0 1
+vn 0 0 1
+f 5/1/1 6/2/1 7/3/1
+").unwrap();
+        std::fs::write(directory.join("broken.obj"), "v 0 0 0\nv 1 0 0\nf 1 2 9\n").unwrap();
+
+        let obj = Obj::load(directory.join("two.obj").to_str().unwrap()).unwrap();
+        assert!(obj.material_error.is_none());
+        assert_eq!(obj.models.len(), 2);
+
+        let plain = obj.model("plain").unwrap();
+        assert!(obj.material(plain).is_none());
+        assert_eq!(plain.normals.len(), plain.vertices.len());
+        assert_eq!(plain.texcoords.len(), plain.vertices.len());
+        for normal in &plain.normals {
+            assert!((normal - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-6);
+        }
+
+        let red = obj.model("red").unwrap();
+        assert_eq!(obj.material(red).unwrap().name, "red");
+        let mesh = obj.get_model_mesh(red);
+        assert_eq!(mesh.vertices[0].color.to_hex(), 0xff0000);
+        assert_eq!(mesh.vertices[1].tex_coords, Vec2::new(1.0, 0.0));
+        let all = obj.get_mesh();
+        assert_eq!((all.vertices.len(), all.triangle_count()), (7, 3));
+        assert_eq!(&all.indices[6..], &[4, 5, 6]);
+
+        let missing = directory.join("missing.obj");
+        assert!(matches!(Obj::load(missing.to_str().unwrap()), Err(ObjError::Load(..))));
+        let broken = Obj::load(directory.join("broken.obj").to_str().unwrap());
+        assert!(matches!(broken, Err(ObjError::Load(_, tobj::LoadError::FaceVertexOutOfBounds))));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
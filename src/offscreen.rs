@@ -0,0 +1,53 @@
+use nalgebra_glm::{Mat4, Vec3};
+
+use crate::matrix::{create_perspective_matrix, create_view_matrix, create_viewport_matrix};
+use crate::obj::PlanetMeshes;
+use crate::registry::ShaderRegistry;
+use crate::screen::framebuffer::Framebuffer;
+use crate::uniforms::{render, CullMode, Uniforms, Winding};
+
+// Everything that determines a headless frame; the same settings always
+// produce the same image
+#[derive(Debug, Clone)]
+pub struct OffscreenSettings {
+    pub planet: String,
+    pub eye: Vec3,
+    pub center: Vec3,
+    pub width: usize,
+    pub height: usize,
+    pub frame: u32,
+}
+
+impl Default for OffscreenSettings {
+    fn default() -> Self {
+        OffscreenSettings {
+            planet: "earth".to_string(),
+            eye: Vec3::new(0.0, 1.0, -3.0),
+            center: Vec3::new(0.0, 0.0, 0.0),
+            width: 800,
+            height: 600,
+            frame: 0,
+        }
+    }
+}
+
+pub fn render_offscreen(settings: &OffscreenSettings, registry: &ShaderRegistry, meshes: &PlanetMeshes) -> Result<Framebuffer, String> {
+    let program = registry.get(&settings.planet)
+        .ok_or_else(|| format!("unknown planet '{}'", settings.planet))?;
+
+    let uniforms = Uniforms {
+        model_matrix: Mat4::identity(),
+        view_matrix: create_view_matrix(settings.eye, settings.center, Vec3::new(0.0, 1.0, 0.0)),
+        projection_matrix: create_perspective_matrix(settings.width as f32, settings.height as f32),
+        viewport_matrix: create_viewport_matrix(settings.width as f32, settings.height as f32),
+        light_dir: Vec3::new(1.0, 3.0, -4.0),
+        time: settings.frame,
+        noise: program.create_noise(),
+        cull_mode: meshes.cull_mode(&program.name).unwrap_or(CullMode::Back),
+        front_face: Winding::CounterClockwise,
+    };
+
+    let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+    render(&mut framebuffer, &uniforms, meshes.for_planet(&program.name), program);
+    Ok(framebuffer)
+}
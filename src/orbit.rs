@@ -0,0 +1,215 @@
+use std::f64::consts::TAU;
+
+use nalgebra_glm::Vec3;
+
+// Classical Keplerian elements of a body around its parent. Angles are in
+// radians, the period in simulated days and the semi-major axis in scene units.
+// The reference plane is the scene's xz plane with y pointing north, so
+// prograde orbits run counter-clockwise seen from +y.
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f32,
+    pub eccentricity: f32,
+    pub inclination: f32,
+    pub ascending_node: f32,
+    pub argument_of_periapsis: f32,
+    pub mean_anomaly_at_epoch: f32,
+    pub period: f32,
+}
+
+impl OrbitalElements {
+    // A circular orbit in the reference plane
+    pub fn circular(radius: f32, period: f32) -> Self {
+        OrbitalElements {
+            semi_major_axis: radius,
+            eccentricity: 0.0,
+            inclination: 0.0,
+            ascending_node: 0.0,
+            argument_of_periapsis: 0.0,
+            mean_anomaly_at_epoch: 0.0,
+            period,
+        }
+    }
+
+    pub fn mean_anomaly(&self, days: f64) -> f32 {
+        let turns = days / self.period as f64;
+        let anomaly = self.mean_anomaly_at_epoch as f64 + TAU * turns.fract();
+        anomaly.rem_euclid(TAU) as f32
+    }
+
+    // Position relative to the parent body after `days` of simulated time
+    pub fn position(&self, days: f64) -> Vec3 {
+        let e = self.eccentricity;
+        let a = self.semi_major_axis;
+        let eccentric = eccentric_anomaly(self.mean_anomaly(days), e);
+
+        // Position in the orbital plane, periapsis along +x
+        let px = a * (eccentric.cos() - e);
+        let py = a * (1.0 - e * e).sqrt() * eccentric.sin();
+
+        let (sin_w, cos_w) = self.argument_of_periapsis.sin_cos();
+        let (sin_o, cos_o) = self.ascending_node.sin_cos();
+        let (sin_i, cos_i) = self.inclination.sin_cos();
+
+        // Rotate by the argument of periapsis, the inclination and the
+        // longitude of the ascending node
+        let x1 = cos_w * px - sin_w * py;
+        let y1 = sin_w * px + cos_w * py;
+        let y2 = cos_i * y1;
+        let north = sin_i * y1;
+        let x = cos_o * x1 - sin_o * y2;
+        let y = sin_o * x1 + cos_o * y2;
+
+        // Ecliptic (x, y, north) to scene axes, keeping the frame right-handed
+        Vec3::new(x, north, -y)
+    }
+}
+
+// Spin of a body about its own axis. The period is the sidereal day in
+// simulated days and the tilt is in radians; a tilt past 90 degrees makes
+// the spin retrograde, as for Venus.
+#[derive(Debug, Clone, Copy)]
+pub struct AxialRotation {
+    pub period: f32,
+    pub axial_tilt: f32,
+}
+
+impl AxialRotation {
+    // Spin about y followed by the tilt about z, in the layout
+    // create_model_matrix expects
+    pub fn rotation(&self, days: f64) -> Vec3 {
+        let turns = (days / self.period as f64).rem_euclid(1.0);
+        Vec3::new(0.0, (TAU * turns) as f32, self.axial_tilt)
+    }
+}
+
+// Solves Kepler's equation M = E - e sin E with Newton's method
+pub fn eccentric_anomaly(mean_anomaly: f32, eccentricity: f32) -> f32 {
+    let m = mean_anomaly as f64;
+    let e = eccentricity as f64;
+    let mut eccentric = if e > 0.8 { std::f64::consts::PI } else { m };
+    for _ in 0..20 {
+        let delta = (eccentric - e * eccentric.sin() - m) / (1.0 - e * eccentric.cos());
+        eccentric -= delta;
+        if delta.abs() < 1e-12 {
+            break;
+        }
+    }
+    eccentric as f32
+}
+
+// Simulated time in days. At 1x one simulated day passes per real second;
+// the rate's sign sets the direction.
+#[derive(Debug, Clone, Copy)]
+pub struct SimulationClock {
+    pub days: f64,
+    pub rate: f64,
+    pub paused: bool,
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        SimulationClock { days: 0.0, rate: 1.0, paused: false }
+    }
+}
+
+impl SimulationClock {
+    pub fn advance(&mut self, seconds: f64) {
+        if !self.paused {
+            self.days += self.rate * seconds;
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // Changes the speed but keeps the current direction
+    pub fn set_speed(&mut self, multiplier: f64) {
+        self.rate = multiplier.abs() * self.rate.signum();
+    }
+
+    pub fn reverse(&mut self) {
+        self.rate = -self.rate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eccentric_orbit() -> OrbitalElements {
+        OrbitalElements {
+            eccentricity: 0.6,
+            ..OrbitalElements::circular(2.0, 10.0)
+        }
+    }
+
+    #[test]
+    fn kepler_solver_satisfies_equation() {
+        for e in [0.0, 0.2, 0.6, 0.95] {
+            for step in 0..16 {
+                let m = step as f32 * 0.4;
+                let eccentric = eccentric_anomaly(m, e);
+                let residual = eccentric - e * eccentric.sin() - m;
+                assert!(residual.abs() < 1e-4, "e {} M {} residual {}", e, m, residual);
+            }
+        }
+    }
+
+    #[test]
+    fn apsides_match_eccentricity() {
+        let orbit = eccentric_orbit();
+        let periapsis = orbit.position(0.0);
+        let apoapsis = orbit.position(5.0);
+        assert!((periapsis.magnitude() - 2.0 * 0.4).abs() < 1e-4);
+        assert!((apoapsis.magnitude() - 2.0 * 1.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn orbit_repeats_after_one_period() {
+        let orbit = OrbitalElements {
+            inclination: 0.3,
+            ascending_node: 1.0,
+            argument_of_periapsis: 2.0,
+            mean_anomaly_at_epoch: 0.5,
+            ..eccentric_orbit()
+        };
+        for days in [0.0, 3.0, -7.5] {
+            let distance = (orbit.position(days) - orbit.position(days + 10.0)).magnitude();
+            assert!(distance < 1e-4);
+        }
+    }
+
+    #[test]
+    fn inclination_lifts_orbit_out_of_plane() {
+        let flat = OrbitalElements::circular(1.0, 4.0);
+        let tilted = OrbitalElements { inclination: 0.5, ..flat };
+        assert!(flat.position(1.0).y.abs() < 1e-6);
+        assert!((tilted.position(1.0).y - 0.5f32.sin()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn spin_completes_a_turn_per_period() {
+        let spin = AxialRotation { period: 0.5, axial_tilt: 0.4 };
+        assert!(spin.rotation(0.0).y.abs() < 1e-6);
+        assert!((spin.rotation(0.125).y - std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+        assert!(spin.rotation(10.0).y.abs() < 1e-5);
+        assert_eq!(spin.rotation(3.3).z, 0.4);
+    }
+
+    #[test]
+    fn clock_pauses_and_reverses() {
+        let mut clock = SimulationClock::default();
+        clock.advance(2.0);
+        clock.toggle_pause();
+        clock.advance(5.0);
+        assert_eq!(clock.days, 2.0);
+
+        clock.toggle_pause();
+        clock.reverse();
+        clock.set_speed(1000.0);
+        clock.advance(0.5);
+        assert_eq!(clock.days, -498.0);
+    }
+}
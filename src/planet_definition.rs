@@ -0,0 +1,577 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use fastnoise_lite::{CellularDistanceFunction, DomainWarpType, FastNoiseLite, FractalType, NoiseType};
+use serde::{Deserialize, Deserializer};
+
+use crate::displacement::{Displacement, Relief};
+use crate::fragments::Fragment;
+use crate::registry::ShaderProgram;
+use crate::screen::color::Color;
+use crate::shader::{color_ramp, surface_point, FragmentShader, LightingClamp};
+use crate::uniforms::Uniforms;
+
+// A planet described in a TOML file instead of Rust: one noise generator,
+// a stack of layers each mapping a pattern through a color ramp, and the
+// range the light intensity is clamped to. See assets/planets for examples.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanetDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub noise: NoiseSettings,
+    pub layers: Vec<Layer>,
+    #[serde(default)]
+    pub lighting: Lighting,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseSettings {
+    #[serde(rename = "type")]
+    pub noise_type: NoiseKind,
+    pub seed: i32,
+    pub frequency: f32,
+    pub fractal: FractalKind,
+    pub octaves: Option<i32>,
+    pub lacunarity: Option<f32>,
+    pub gain: Option<f32>,
+    pub weighted_strength: Option<f32>,
+    pub cellular_distance: Option<CellularDistance>,
+    pub warp: Option<WarpKind>,
+    pub warp_amplitude: Option<f32>,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        NoiseSettings {
+            noise_type: NoiseKind::OpenSimplex2,
+            seed: 1337,
+            frequency: 0.01,
+            fractal: FractalKind::None,
+            octaves: None,
+            lacunarity: None,
+            gain: None,
+            weighted_strength: None,
+            cellular_distance: None,
+            warp: None,
+            warp_amplitude: None,
+        }
+    }
+}
+
+impl NoiseSettings {
+    pub fn create_noise(&self) -> FastNoiseLite {
+        let mut noise = FastNoiseLite::with_seed(self.seed);
+        noise.set_noise_type(Some(self.noise_type.into()));
+        noise.set_frequency(Some(self.frequency));
+        noise.set_fractal_type(Some(self.fractal.into()));
+        noise.set_fractal_octaves(self.octaves);
+        noise.set_fractal_lacunarity(self.lacunarity);
+        noise.set_fractal_gain(self.gain);
+        noise.set_fractal_weighted_strength(self.weighted_strength);
+        noise.set_cellular_distance_function(self.cellular_distance.map(Into::into));
+        noise.set_domain_warp_type(self.warp.map(Into::into));
+        noise.set_domain_warp_amp(self.warp_amplitude);
+        noise
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    OpenSimplex2,
+    OpenSimplex2s,
+    Cellular,
+    Perlin,
+    ValueCubic,
+    Value,
+}
+
+impl From<NoiseKind> for NoiseType {
+    fn from(kind: NoiseKind) -> Self {
+        match kind {
+            NoiseKind::OpenSimplex2 => NoiseType::OpenSimplex2,
+            NoiseKind::OpenSimplex2s => NoiseType::OpenSimplex2S,
+            NoiseKind::Cellular => NoiseType::Cellular,
+            NoiseKind::Perlin => NoiseType::Perlin,
+            NoiseKind::ValueCubic => NoiseType::ValueCubic,
+            NoiseKind::Value => NoiseType::Value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FractalKind {
+    None,
+    Fbm,
+    Ridged,
+    PingPong,
+}
+
+impl From<FractalKind> for FractalType {
+    fn from(kind: FractalKind) -> Self {
+        match kind {
+            FractalKind::None => FractalType::None,
+            FractalKind::Fbm => FractalType::FBm,
+            FractalKind::Ridged => FractalType::Ridged,
+            FractalKind::PingPong => FractalType::PingPong,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellularDistance {
+    Euclidean,
+    EuclideanSq,
+    Manhattan,
+    Hybrid,
+}
+
+impl From<CellularDistance> for CellularDistanceFunction {
+    fn from(distance: CellularDistance) -> Self {
+        match distance {
+            CellularDistance::Euclidean => CellularDistanceFunction::Euclidean,
+            CellularDistance::EuclideanSq => CellularDistanceFunction::EuclideanSq,
+            CellularDistance::Manhattan => CellularDistanceFunction::Manhattan,
+            CellularDistance::Hybrid => CellularDistanceFunction::Hybrid,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarpKind {
+    OpenSimplex2,
+    OpenSimplex2Reduced,
+    BasicGrid,
+}
+
+impl From<WarpKind> for DomainWarpType {
+    fn from(kind: WarpKind) -> Self {
+        match kind {
+            WarpKind::OpenSimplex2 => DomainWarpType::OpenSimplex2,
+            WarpKind::OpenSimplex2Reduced => DomainWarpType::OpenSimplex2Reduced,
+            WarpKind::BasicGrid => DomainWarpType::BasicGrid,
+        }
+    }
+}
+
+// What a layer measures at each point, always in 0..1:
+// noise samples the planet's noise, bands are stripes along the latitude
+// (roughened by `turbulence`) and latitude is 0 at the equator, 1 at the poles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    #[default]
+    Noise,
+    Bands,
+    Latitude,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Subtract,
+}
+
+impl BlendMode {
+    pub fn blend(self, base: &Color, layer: &Color) -> Color {
+        match self {
+            BlendMode::Normal => *layer,
+            BlendMode::Add => base.blend_add(layer),
+            BlendMode::Multiply => base.blend_multiply(layer),
+            BlendMode::Screen => base.blend_screen(layer),
+            BlendMode::Subtract => base.blend_subtract(layer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColorStop {
+    at: f32,
+    #[serde(deserialize_with = "hex_color")]
+    color: Color,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layer {
+    #[serde(default)]
+    pub pattern: Pattern,
+    #[serde(default)]
+    pub blend: BlendMode,
+    #[serde(default = "one")]
+    pub opacity: f32,
+    // Multiplies the surface point before sampling the noise
+    #[serde(default = "one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub offset: [f32; 3],
+    // Offset added per frame, so the layer flows over the surface
+    #[serde(default)]
+    pub drift: [f32; 3],
+    // Number of stripes from pole to pole, for bands
+    #[serde(default = "one")]
+    pub frequency: f32,
+    #[serde(default)]
+    pub turbulence: f32,
+    // The layer is only drawn where its pattern falls inside [min, max]
+    pub mask: Option<[f32; 2]>,
+    // (at, color) stops, written as { at, color = "#rrggbb" }
+    #[serde(deserialize_with = "color_stops")]
+    pub ramp: Vec<(f32, Color)>,
+}
+
+impl Layer {
+    fn sample_noise(&self, fragment: &Fragment, uniforms: &Uniforms, warp: bool) -> f32 {
+        let p = surface_point(fragment) * self.scale;
+        let t = uniforms.time as f32;
+        let mut x = p.x + self.offset[0] + self.drift[0] * t;
+        let mut y = p.y + self.offset[1] + self.drift[1] * t;
+        let mut z = p.z + self.offset[2] + self.drift[2] * t;
+        if warp {
+            (x, y, z) = uniforms.noise.domain_warp_3d(x, y, z);
+        }
+        ((uniforms.noise.get_noise_3d(x, y, z) + 1.0) / 2.0).clamp(0.0, 1.0)
+    }
+
+    pub fn pattern_value(&self, fragment: &Fragment, uniforms: &Uniforms, warp: bool) -> f32 {
+        let latitude = fragment.vertex_position.try_normalize(f32::EPSILON).map_or(0.0, |n| n.y);
+        match self.pattern {
+            Pattern::Noise => self.sample_noise(fragment, uniforms, warp),
+            Pattern::Bands => {
+                let roughness = if self.turbulence != 0.0 {
+                    (self.sample_noise(fragment, uniforms, warp) - 0.5) * self.turbulence
+                } else {
+                    0.0
+                };
+                ((latitude + roughness) * self.frequency * std::f32::consts::PI).sin() * 0.5 + 0.5
+            }
+            Pattern::Latitude => latitude.abs(),
+        }
+    }
+
+    pub fn ramp_color(&self, value: f32) -> Color {
+        color_ramp(&self.ramp, value)
+    }
+
+    pub fn apply(&self, base: Color, fragment: &Fragment, uniforms: &Uniforms, warp: bool) -> Color {
+        let value = self.pattern_value(fragment, uniforms, warp);
+        if let Some([min, max]) = self.mask {
+            if value < min || value > max {
+                return base;
+            }
+        }
+        let blended = self.blend.blend(&base, &self.ramp_color(value));
+        base.lerp(&blended, self.opacity)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lighting {
+    pub min: f32,
+    pub max: f32,
+}
+
+// Files without a [lighting] table are lit like the built-in shaders
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting { min: LightingClamp::DEFAULT.min, max: LightingClamp::DEFAULT.max }
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+// Colors are written as "#rrggbb"
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_hex_color(&text).ok_or_else(|| serde::de::Error::custom(format!("invalid color '{}', expected \"#rrggbb\"", text)))
+}
+
+fn color_stops<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(f32, Color)>, D::Error> {
+    let stops = Vec::<ColorStop>::deserialize(deserializer)?;
+    Ok(stops.into_iter().map(|stop| (stop.at, stop.color)).collect())
+}
+
+fn parse_hex_color(text: &str) -> Option<Color> {
+    let digits = text.strip_prefix('#')?;
+    // from_str_radix would also take a sign such as "#+12345"
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok().map(Color::from_hex)
+}
+
+#[derive(Debug)]
+pub enum PlanetFileError {
+    Io(PathBuf, io::Error),
+    // The TOML doesn't parse or describes a planet the shader cannot draw
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for PlanetFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanetFileError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            PlanetFileError::Invalid(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for PlanetFileError {}
+
+impl PlanetDefinition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut definition: PlanetDefinition = toml::from_str(text).map_err(|e| e.to_string())?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PlanetFileError> {
+        let text = fs::read_to_string(path).map_err(|e| PlanetFileError::Io(path.to_path_buf(), e))?;
+        Self::parse(&text).map_err(|message| PlanetFileError::Invalid(path.to_path_buf(), message))
+    }
+
+    // Sorts the ramps and rejects what the shader cannot draw. TOML accepts
+    // nan and inf, which would slip past the range checks below.
+    fn validate(&mut self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if self.layers.is_empty() {
+            return Err("a planet needs at least one layer".to_string());
+        }
+        require_finite("noise.frequency", &[self.noise.frequency])?;
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            let field = |name: &str| format!("layer {} {}", index + 1, name);
+            if layer.ramp.is_empty() {
+                return Err(format!("layer {} has an empty ramp", index + 1));
+            }
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(format!("layer {} opacity must be between 0 and 1", index + 1));
+            }
+            for (at, _) in &layer.ramp {
+                require_finite(&field("ramp at"), &[*at])?;
+            }
+            require_finite(&field("scale"), &[layer.scale])?;
+            require_finite(&field("offset"), &layer.offset)?;
+            require_finite(&field("drift"), &layer.drift)?;
+            require_finite(&field("frequency"), &[layer.frequency])?;
+            require_finite(&field("turbulence"), &[layer.turbulence])?;
+            require_finite(&field("mask"), &layer.mask.unwrap_or_default())?;
+            layer.ramp.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        require_finite("lighting", &[self.lighting.min, self.lighting.max])?;
+        if self.lighting.min > self.lighting.max {
+            return Err("lighting.min must not exceed lighting.max".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn into_program(self) -> ShaderProgram {
+        let noise = self.noise.clone();
+        let name = self.name.clone();
+        ShaderProgram::new(&name, self, move || noise.create_noise())
+    }
+}
+
+fn require_finite(name: &str, values: &[f32]) -> Result<(), String> {
+    if values.iter().all(|value| value.is_finite()) {
+        Ok(())
+    } else {
+        Err(format!("{} must be a finite number", name))
+    }
+}
+
+impl FragmentShader for PlanetDefinition {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let warp = self.noise.warp.is_some();
+        let color = self.layers.iter()
+            .fold(Color::black(), |base, layer| layer.apply(base, fragment, uniforms, warp));
+        color * self.lighting().clamp(fragment.intensity)
+    }
+
+    fn lighting(&self) -> LightingClamp {
+        LightingClamp::new(self.lighting.min, self.lighting.max)
+    }
+
+    // Hills following the first noise layer at its scale
+    fn displacement(&self) -> Displacement {
+        match self.layers.iter().find(|layer| layer.pattern == Pattern::Noise) {
+            Some(layer) => Displacement { relief: Relief::Hills { zoom: layer.scale }, ..Displacement::default() },
+            None => Displacement::default(),
+        }
+    }
+}
+
+// Every *.toml file in `directory` that loads, sorted by file name, and the
+// errors of the ones that don't. A broken file doesn't stop the others.
+pub fn load_planet_directory(directory: &Path) -> (Vec<PlanetDefinition>, Vec<PlanetFileError>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => return (Vec::new(), vec![PlanetFileError::Io(directory.to_path_buf(), error)]),
+    };
+    let mut paths = Vec::new();
+    let mut errors = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+                if path.extension().is_some_and(|extension| extension == "toml") {
+                    paths.push(path);
+                }
+            }
+            Err(error) => errors.push(PlanetFileError::Io(directory.to_path_buf(), error)),
+        }
+    }
+    paths.sort();
+
+    let mut definitions = Vec::new();
+    for path in paths {
+        match PlanetDefinition::load(&path) {
+            Ok(definition) => definitions.push(definition),
+            Err(error) => errors.push(error),
+        }
+    }
+    (definitions, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uniforms::{CullMode, Winding};
+    use nalgebra_glm::{Mat4, Vec2, Vec3};
+
+    const SAMPLE: &str = r##"
+        name = "sample"
+
+        [noise]
+        type = "value_cubic"
+        fractal = "fbm"
+        octaves = 3
+
+        [[layers]]
+        ramp = [{ at = 1.0, color = "#ffffff" }, { at = 0.0, color = "#000000" }]
+
+        [[layers]]
+        pattern = "latitude"
+        mask = [0.9, 1.0]
+        ramp = [{ at = 0.0, color = "#ff0000" }]
+
+        [lighting]
+        min = 1.0
+        max = 1.0
+    "##;
+
+    fn fragment_at(position: Vec3) -> Fragment {
+        Fragment::new(Vec2::new(0.0, 0.0), Color::black(), 0.0, position, 0.5, position, Vec2::new(0.0, 0.0))
+    }
+
+    #[test]
+    fn parses_and_sorts_ramps() {
+        let definition = PlanetDefinition::parse(SAMPLE).unwrap();
+        assert_eq!(definition.name, "sample");
+        assert_eq!(definition.noise.noise_type, NoiseKind::ValueCubic);
+        assert_eq!(definition.noise.seed, 1337);
+        assert_eq!(definition.layers[0].ramp[0].0, 0.0);
+        assert_eq!(definition.layers[1].pattern, Pattern::Latitude);
+    }
+
+    #[test]
+    fn ramp_interpolates_between_stops() {
+        let definition = PlanetDefinition::parse(SAMPLE).unwrap();
+        let gray = definition.layers[0].ramp_color(0.5);
+        assert_eq!((gray.r, gray.g, gray.b), (128, 128, 128));
+        assert_eq!(definition.layers[0].ramp_color(2.0).r, 255);
+    }
+
+    #[test]
+    fn masked_layer_only_covers_the_poles() {
+        let definition = PlanetDefinition::parse(SAMPLE).unwrap();
+        let uniforms = Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            light_dir: Vec3::new(0.0, 1.0, 0.0),
+            time: 0,
+            noise: definition.noise.create_noise(),
+            cull_mode: CullMode::Back,
+            front_face: Winding::CounterClockwise,
+        };
+        let pole = definition.shade(&fragment_at(Vec3::new(0.0, 0.5, 0.0)), &uniforms);
+        let equator = definition.shade(&fragment_at(Vec3::new(0.5, 0.0, 0.0)), &uniforms);
+        assert_eq!((pole.r, pole.g, pole.b), (255, 0, 0));
+        assert_eq!(equator.r, equator.g);
+    }
+
+    #[test]
+    fn lighting_defaults_to_the_built_in_clamp() {
+        let definition = PlanetDefinition::parse("name = \"x\"\n[[layers]]\nramp = [{ at = 0.0, color = \"#ffffff\" }]").unwrap();
+        assert_eq!(definition.lighting(), LightingClamp::DEFAULT);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(PlanetDefinition::parse("name = \"x\"\nlayers = []").is_err());
+        assert!(PlanetDefinition::parse("name = \"x\"\n[[layers]]\nramp = [{ at = 0.0, color = \"red\" }]").is_err());
+        assert!(PlanetDefinition::parse("name = \"x\"\ncolour = 1\n[[layers]]\nramp = [{ at = 0.0, color = \"#ff0000\" }]").is_err());
+        assert!(parse_hex_color("#+12345").is_none());
+        assert_eq!(parse_hex_color("#12abEF").map(|c| c.to_hex()), Some(0x12abef));
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_finite() {
+        let planet = |layer: &str, rest: &str| format!("name = \"x\"\n[[layers]]\n{}\n{}", layer, rest);
+        let ramp = "ramp = [{ at = 0.0, color = \"#ffffff\" }]";
+        let files = [
+            planet(ramp, "[lighting]\nmin = nan\nmax = 1.0"),
+            planet(ramp, "[lighting]\nmin = 0.0\nmax = inf"),
+            planet(ramp, "[noise]\nfrequency = nan"),
+            planet(&format!("scale = nan\n{}", ramp), ""),
+            planet(&format!("frequency = nan\n{}", ramp), ""),
+            planet(&format!("turbulence = -inf\n{}", ramp), ""),
+            planet("ramp = [{ at = nan, color = \"#ffffff\" }]", ""),
+        ];
+        for text in &files {
+            let error = PlanetDefinition::parse(text).unwrap_err();
+            assert!(error.contains("must be a finite number"), "{}\n{}", text, error);
+        }
+    }
+
+    #[test]
+    fn bundled_planets_load() {
+        let (definitions, errors) = load_planet_directory(Path::new("./assets/planets"));
+        assert!(errors.is_empty());
+        let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+        assert!(names.contains(&"mars") && names.contains(&"uranus"));
+    }
+
+    #[test]
+    fn a_broken_file_keeps_the_others() {
+        let directory = std::env::temp_dir().join(format!("planet_directory_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("a.toml"), "name = ").unwrap();
+        fs::write(directory.join("b.toml"), SAMPLE).unwrap();
+
+        let (definitions, errors) = load_planet_directory(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].name, "sample");
+        assert!(matches!(&errors[..], [PlanetFileError::Invalid(path, _)] if path.ends_with("a.toml")));
+    }
+}
@@ -0,0 +1,334 @@
+use std::f32::consts::PI;
+
+use nalgebra_glm::Vec3;
+
+use crate::planet_definition::BlendMode;
+use crate::screen::color::Color;
+use crate::shader::{
+    LightingClamp, EARTH_BIOME_ZOOM, EARTH_CLOUD_DETAIL_ZOOM, EARTH_CLOUD_LEVEL, EARTH_CLOUD_ZOOM,
+    EARTH_COAST_ZOOM, EARTH_CONTINENT_ZOOM, EARTH_RELIEF, EARTH_DESERT_LEVEL, EARTH_GREENERY_LEVEL, EARTH_LAND_LEVEL,
+    EARTH_NOISE_OFFSET, JUPITER_BAND_ZOOM, JUPITER_DARK_LEVEL, JUPITER_LIGHTING, JUPITER_LIGHT_BAND_Y_ZOOM,
+    JUPITER_OTHER_BAND_Y_ZOOM, JUPITER_SPOT_CENTER, JUPITER_SPOT_RADIUS, JUPITER_SPOT_RAMP, JUPITER_SPOT_ZOOM,
+    JUPITER_STRIPE_LEVEL, JUPITER_STRIPE_WIDTH, MERCURY_BLUE_ZOOM, MERCURY_CRATER_FLOOR, MERCURY_CRATER_RIM,
+    MERCURY_CRATER_ZOOM, MERCURY_GREEN_ZOOM, MERCURY_LIGHTING, MERCURY_RED_ZOOM, MERCURY_RELIEF, MOON_DISTANCE, MOON_RAMP, MOON_RELIEF, MOON_ZOOM,
+    NEPTUNE_AREA_ZOOM, NEPTUNE_ZOOM, SATURN_BAND_HEIGHT, SATURN_LIGHTING, SATURN_SHADOW_LEVEL,
+    SATURN_STRIPE_LEVEL, SATURN_STRIPE_WIDTH, SATURN_TEXTURE_Y_ZOOM, SATURN_TEXTURE_ZOOM, SUN_BRIGHT_LEVEL,
+    SUN_CELL_ZOOM, SUN_DARK_LEVEL, SUN_LIGHTING, VENUS_AREA_ZOOM, VENUS_CLOUD_LEVEL, VENUS_CLOUD_ZOOM,
+    VENUS_LIGHTING, VENUS_TEXTURE_LEVEL, VENUS_TEXTURE_ZOOM,
+};
+use crate::shader_graph::{Axis, Compare, GraphBuilder, NodeId, NoiseSample, ShaderGraph};
+
+// The hand-written planet shaders from shader.rs rebuilt as node graphs.
+// Every graph gives exactly the same colors as its shader.
+
+// color * (intensity clamped to the lighting's min..max)
+fn lit(g: &mut GraphBuilder, color: NodeId, lighting: LightingClamp) -> NodeId {
+    let intensity = g.intensity();
+    let intensity = g.clamp(intensity, lighting.min, lighting.max);
+    g.scale_color(color, intensity)
+}
+
+// Noise remapped to 0..1 and clamped there
+fn clamped_noise(g: &mut GraphBuilder, sample: NoiseSample) -> NodeId {
+    let noise = g.noise01(sample);
+    g.clamp(noise, 0.0, 1.0)
+}
+
+// hex * (constant op value), the shaders' usual way of shading by noise
+fn hex_times(g: &mut GraphBuilder, hex: u32, factor: NodeId) -> NodeId {
+    let color = g.color(Color::from_hex(hex));
+    g.scale_color(color, factor)
+}
+
+fn constant_minus(g: &mut GraphBuilder, constant: f32, value: NodeId) -> NodeId {
+    let constant = g.constant(constant);
+    g.sub(constant, value)
+}
+
+fn plus_constant(g: &mut GraphBuilder, value: NodeId, constant: f32) -> NodeId {
+    let constant = g.constant(constant);
+    g.add(value, constant)
+}
+
+fn times_constant(g: &mut GraphBuilder, value: NodeId, constant: f32) -> NodeId {
+    let constant = g.constant(constant);
+    g.mul(value, constant)
+}
+
+// sin((surface.y / width) * PI) * 0.5 + 0.5
+fn latitude_stripes(g: &mut GraphBuilder, width: f32) -> NodeId {
+    let y = g.surface(Axis::Y);
+    let width = g.constant(width);
+    let y = g.div(y, width);
+    let angle = times_constant(g, y, PI);
+    let wave = g.sin(angle);
+    let wave = times_constant(g, wave, 0.5);
+    plus_constant(g, wave, 0.5)
+}
+
+// Noise remapped to 0..1 and colored along a ramp, shared by the moon and
+// the Great Red Spot
+fn speckled(g: &mut GraphBuilder, stops: &[(f32, Color)], sample: NoiseSample) -> NodeId {
+    let noise = g.noise01(sample);
+    g.color_ramp(noise, stops.to_vec())
+}
+
+pub fn sun_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let cells = g.noise01(
+        NoiseSample::scaled(SUN_CELL_ZOOM)
+            .with_shift(Vec3::new(50.0, 50.0, 0.0))
+            .with_shift_speed(Vec3::new(1.0, 0.0, 0.0)),
+    );
+    let dark_factor = plus_constant(&mut g, cells, 0.5);
+    let dark = hex_times(&mut g, 0xff2a00, dark_factor);
+    let normal_factor = plus_constant(&mut g, cells, 0.4);
+    let normal = hex_times(&mut g, 0xff5100, normal_factor);
+    let white_factor = plus_constant(&mut g, cells, 0.2);
+    let white = hex_times(&mut g, 0xffffff, white_factor);
+
+    let bright = g.select(cells, Compare::Less, SUN_BRIGHT_LEVEL, normal, white);
+    let spots = g.select(cells, Compare::Less, SUN_DARK_LEVEL, dark, bright);
+    let tint = g.color(Color::from_hex(0xffe0ad));
+    let output = g.blend(BlendMode::Multiply, spots, tint);
+    g.build(output).with_lighting(SUN_LIGHTING)
+}
+
+fn moon_surface(g: &mut GraphBuilder) -> NodeId {
+    speckled(g, &MOON_RAMP, NoiseSample::scaled(MOON_ZOOM))
+}
+
+pub fn moon_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let surface = moon_surface(&mut g);
+    let output = lit(&mut g, surface, LightingClamp::DEFAULT);
+    g.build(output).with_displacement(MOON_RELIEF)
+}
+
+pub fn earth_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let offset = Vec3::new(EARTH_NOISE_OFFSET, EARTH_NOISE_OFFSET, EARTH_NOISE_OFFSET);
+
+    // Continents
+    let big = clamped_noise(&mut g, NoiseSample::scaled(EARTH_CONTINENT_ZOOM).with_offset(offset));
+    let small = clamped_noise(&mut g, NoiseSample::scaled(EARTH_COAST_ZOOM).with_offset(offset));
+    let big = times_constant(&mut g, big, 0.7);
+    let small = times_constant(&mut g, small, 0.3);
+    let land = g.add(big, small);
+
+    // Biomes
+    let biome = clamped_noise(&mut g, NoiseSample::scaled(EARTH_BIOME_ZOOM).with_offset(offset));
+    let desert = g.color(Color::from_hex(0x7d6902));
+    let greenery = g.color(Color::from_hex(0x053300));
+    let dry = g.scale_color(desert, biome);
+    let mixed = g.blend(BlendMode::Add, greenery, dry);
+    let wet = g.select(biome, Compare::Less, EARTH_GREENERY_LEVEL, mixed, greenery);
+    let biomes = g.select(biome, Compare::Less, EARTH_DESERT_LEVEL, desert, wet);
+    let ocean = g.color(Color::from_hex(0x000d47));
+    let map = g.select(land, Compare::Less, EARTH_LAND_LEVEL, biomes, ocean);
+
+    // Clouds drifting along x
+    let big = clamped_noise(&mut g, NoiseSample::scaled(EARTH_CLOUD_ZOOM).with_shift_speed(Vec3::new(4.0, 0.0, 0.0)));
+    let small = clamped_noise(&mut g, NoiseSample::scaled(EARTH_CLOUD_DETAIL_ZOOM).with_shift_speed(Vec3::new(7.5, 0.0, 0.0)));
+    let small = times_constant(&mut g, small, 0.3);
+    let big = times_constant(&mut g, big, 0.7);
+    let cover = g.add(small, big);
+    let thickness = constant_minus(&mut g, 0.6, cover);
+    let cloud = hex_times(&mut g, 0xffffff, thickness);
+    let clear = g.color(Color::black());
+    let clouds = g.select(cover, Compare::Less, EARTH_CLOUD_LEVEL, cloud, clear);
+    let planet = g.blend(BlendMode::Add, map, clouds);
+
+    // The moon baked into sphere_moon.obj
+    let radius = g.distance_to(Vec3::zeros());
+    let moon = moon_surface(&mut g);
+    let surface = g.select(radius, Compare::Greater, MOON_DISTANCE, moon, planet);
+    let output = lit(&mut g, surface, LightingClamp::DEFAULT);
+    g.build(output).with_displacement(EARTH_RELIEF)
+}
+
+pub fn mercury_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+
+    let crater = clamped_noise(&mut g, NoiseSample::scaled(MERCURY_CRATER_ZOOM));
+    let floor = constant_minus(&mut g, 0.7, crater);
+    let floor = hex_times(&mut g, 0xffffff, floor);
+    let rim = constant_minus(&mut g, 1.0, crater);
+    let rim = hex_times(&mut g, 0xffffff, rim);
+    let plain = g.color(Color::from_hex(0xffffff));
+    let outside = g.select(crater, Compare::Less, MERCURY_CRATER_RIM, rim, plain);
+    let craters = g.select(crater, Compare::Less, MERCURY_CRATER_FLOOR, floor, outside);
+
+    let red = clamped_noise(&mut g, NoiseSample::scaled(MERCURY_RED_ZOOM));
+    let green = clamped_noise(&mut g, NoiseSample::scaled(MERCURY_GREEN_ZOOM).with_offset(Vec3::new(10.0, 0.0, 0.0)));
+    let blue = clamped_noise(&mut g, NoiseSample::scaled(MERCURY_BLUE_ZOOM).with_offset(Vec3::new(20.0, 0.0, 0.0)));
+    let red = hex_times(&mut g, 0xff0000, red);
+    let green = hex_times(&mut g, 0x00ff00, green);
+    let blue = hex_times(&mut g, 0x0000ff, blue);
+    let colors = g.blend(BlendMode::Add, red, green);
+    let colors = g.blend(BlendMode::Add, colors, blue);
+    let intensity = g.intensity();
+    let limit = g.constant(MERCURY_LIGHTING.max);
+    let intensity = g.min(intensity, limit);
+    let colors = g.scale_color(colors, intensity);
+
+    let output = g.blend(BlendMode::Multiply, colors, craters);
+    g.build(output).with_lighting(MERCURY_LIGHTING).with_displacement(MERCURY_RELIEF)
+}
+
+// 0xc28515 * (1 - noise) where the mixed noise is below 0.5, black elsewhere
+fn venus_clouds(g: &mut GraphBuilder, big_offset: Vec3) -> NodeId {
+    let small = g.noise01(NoiseSample::scaled(VENUS_CLOUD_ZOOM));
+    let big = g.noise01(NoiseSample::scaled(1.0).with_offset(big_offset));
+    let big = times_constant(g, big, 0.6);
+    let small = times_constant(g, small, 0.4);
+    let noise = g.add(big, small);
+    let shade = constant_minus(g, 1.0, noise);
+    let cloud = hex_times(g, 0xc28515, shade);
+    let clear = g.color(Color::black());
+    g.select(noise, Compare::Less, VENUS_CLOUD_LEVEL, cloud, clear)
+}
+
+pub fn venus_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let lighter = venus_clouds(&mut g, Vec3::zeros());
+    let darker = venus_clouds(&mut g, Vec3::new(40.0, 0.0, 0.0));
+
+    let fine = g.noise01(NoiseSample::scaled(VENUS_TEXTURE_ZOOM));
+    let area = g.noise01(NoiseSample::scaled(VENUS_AREA_ZOOM));
+    let noise = g.max(area, fine);
+    let shade = constant_minus(&mut g, 1.0, noise);
+    let shaded = hex_times(&mut g, 0xffffff, shade);
+    let white = g.color(Color::from_hex(0xffffff));
+    let texture = g.select(noise, Compare::Less, VENUS_TEXTURE_LEVEL, shaded, white);
+
+    let base = g.color(Color::from_hex(0xd9852b));
+    let color = g.blend(BlendMode::Subtract, base, darker);
+    let color = g.blend(BlendMode::Add, color, lighter);
+    let color = g.blend(BlendMode::Multiply, color, texture);
+    let output = lit(&mut g, color, VENUS_LIGHTING);
+    g.build(output).with_lighting(VENUS_LIGHTING)
+}
+
+// One family of Jupiter's bands, scrolling along x at `speed`
+fn jupiter_band(g: &mut GraphBuilder, fine: NoiseSample, speed: f32, dark: u32, light: u32) -> NodeId {
+    let speed = Vec3::new(speed, 0.0, 0.0);
+    let fine = g.noise01(fine.with_speed(speed));
+    let area = g.noise01(NoiseSample::scaled(1.0).with_speed(speed));
+    let area = times_constant(g, area, 2.0);
+    let noise = g.min(fine, area);
+
+    let shade = constant_minus(g, 1.0, noise);
+    let darker = hex_times(g, dark, shade);
+    let brightness = plus_constant(g, noise, 0.5);
+    let one = g.constant(1.0);
+    let brightness = g.min(brightness, one);
+    let lighter = hex_times(g, light, brightness);
+    g.select(noise, Compare::Less, JUPITER_DARK_LEVEL, darker, lighter)
+}
+
+pub fn jupiter_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let light = jupiter_band(&mut g, NoiseSample::scaled(JUPITER_BAND_ZOOM).with_scale(Vec3::new(JUPITER_BAND_ZOOM, JUPITER_LIGHT_BAND_Y_ZOOM, JUPITER_BAND_ZOOM)), 0.5, 0xffc86b, 0xffd896);
+    let other = jupiter_band(
+        &mut g,
+        NoiseSample::scaled(JUPITER_BAND_ZOOM).with_scale(Vec3::new(JUPITER_BAND_ZOOM, JUPITER_OTHER_BAND_Y_ZOOM, JUPITER_BAND_ZOOM)).with_shift(Vec3::new(200.0, 200.0, 0.0)),
+        -0.5,
+        0xabebff,
+        0xd9f6ff,
+    );
+    let stripes = latitude_stripes(&mut g, JUPITER_STRIPE_WIDTH);
+    let bands = g.select(stripes, Compare::Less, JUPITER_STRIPE_LEVEL, light, other);
+
+    // Great Red Spot
+    let distance = g.distance_to(JUPITER_SPOT_CENTER);
+    let spot = speckled(&mut g, &JUPITER_SPOT_RAMP, NoiseSample::scaled(JUPITER_SPOT_ZOOM).with_speed(Vec3::new(1.0, 0.0, 0.0)));
+    let surface = g.select(distance, Compare::Less, JUPITER_SPOT_RADIUS, spot, bands);
+    let output = lit(&mut g, surface, JUPITER_LIGHTING);
+    g.build(output).with_lighting(JUPITER_LIGHTING)
+}
+
+pub fn saturn_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let stripes = latitude_stripes(&mut g, SATURN_STRIPE_WIDTH);
+    let pale = g.color(Color::from_hex(0xffd885));
+    let deep = g.color(Color::from_hex(0xff9238));
+    let lines = g.select(stripes, Compare::Less, SATURN_STRIPE_LEVEL, pale, deep);
+
+    // Equatorial band, which also covers the ring
+    let y = g.surface(Axis::Y);
+    let height = g.abs(y);
+    let band = g.color(Color::from_hex(0xff7e33));
+    let color = g.select(height, Compare::Less, SATURN_BAND_HEIGHT, band, lines);
+
+    let noise = g.noise01(NoiseSample::scaled(SATURN_TEXTURE_ZOOM).with_scale(Vec3::new(SATURN_TEXTURE_ZOOM, SATURN_TEXTURE_Y_ZOOM, SATURN_TEXTURE_ZOOM)));
+    let darkened = g.scale_color(color, noise);
+    let textured = g.select(noise, Compare::Less, SATURN_SHADOW_LEVEL, color, darkened);
+    let output = lit(&mut g, textured, SATURN_LIGHTING);
+    g.build(output).with_lighting(SATURN_LIGHTING)
+}
+
+pub fn neptune_graph() -> ShaderGraph {
+    let mut g = GraphBuilder::new();
+    let fine = g.noise01(NoiseSample::scaled(NEPTUNE_ZOOM));
+    let area = g.noise(NoiseSample::scaled(NEPTUNE_AREA_ZOOM));
+    let area = plus_constant(&mut g, area, 1.0);
+    let noise = g.max(area, fine);
+    let color = hex_times(&mut g, 0x1350ba, noise);
+    let output = lit(&mut g, color, LightingClamp::DEFAULT);
+    g.build(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fragments::Fragment;
+    use crate::registry::ShaderRegistry;
+    use crate::uniforms::{CullMode, Uniforms, Winding};
+    use nalgebra_glm::{Mat4, Vec2};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Compares every graph with its hand-written shader on random points of
+    // the sphere, the ring and the baked moon, at several times
+    #[test]
+    fn graphs_match_hand_written_shaders() {
+        let shaders = ShaderRegistry::with_planets();
+        let graphs = ShaderRegistry::with_planet_graphs();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for name in shaders.names() {
+            let shader = shaders.get(name).unwrap();
+            let graph = graphs.get(name).unwrap();
+            assert_eq!(graph.fragment.lighting(), shader.fragment.lighting(), "{} lighting", name);
+            assert_eq!(graph.fragment.displacement(), shader.fragment.displacement(), "{} relief", name);
+            for time in [0, 10, 777] {
+                let uniforms = Uniforms {
+                    model_matrix: Mat4::identity(),
+                    view_matrix: Mat4::identity(),
+                    projection_matrix: Mat4::identity(),
+                    viewport_matrix: Mat4::identity(),
+                    light_dir: Vec3::new(1.0, 3.0, -4.0),
+                    time,
+                    noise: shader.create_noise(),
+                    cull_mode: CullMode::Back,
+                    front_face: Winding::CounterClockwise,
+                };
+                for _ in 0..2000 {
+                    let direction = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                    let radius = [0.5, 0.8, 1.1][rng.gen_range(0..3)];
+                    let position = direction.normalize() * radius;
+                    let intensity = rng.gen_range(-1.0..3.0);
+                    let fragment = Fragment::new(Vec2::new(0.0, 0.0), Color::black(), 0.0, direction, intensity, position, Vec2::new(0.0, 0.0));
+
+                    let expected = shader.fragment.shade(&fragment, &uniforms);
+                    let actual = graph.fragment.shade(&fragment, &uniforms);
+                    assert_eq!(
+                        (actual.r, actual.g, actual.b), (expected.r, expected.g, expected.b),
+                        "{} differs at {:?}, time {}", name, position, time
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use nalgebra_glm::{Vec2, Vec3};
+use crate::obj::{set_spherical_tex_coords, spherical_direction, MESH_COLOR};
+use crate::screen::color::Color;
+use crate::vertex::Vertex;
+
+// Mesh generators. Like Obj::get_vertex_array they return triangle lists,
+// three vertices per triangle, wound counter-clockwise seen from the front.
+
+fn mesh_vertex(position: Vec3, normal: Vec3, tex_coords: Vec2) -> Vertex {
+    Vertex { color: Color::from_hex(MESH_COLOR), ..Vertex::new(position, normal, tex_coords) }
+}
+
+// Pushes a triangle, flipping it if needed so it faces along `outward`
+fn push_facing(vertices: &mut Vec<Vertex>, a: Vertex, b: Vertex, c: Vertex, outward: &Vec3) {
+    let normal = (b.position - a.position).cross(&(c.position - a.position));
+    if normal.dot(outward) < 0.0 {
+        vertices.extend([a, c, b]);
+    } else {
+        vertices.extend([a, b, c]);
+    }
+}
+
+// Latitude/longitude sphere centered on the origin. The texture coordinates
+// follow obj::spherical_tex_coords, so baked and texture maps line up.
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Vec<Vertex> {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let point = |u: f32, v: f32| {
+        let direction = spherical_direction(&Vec2::new(u, v));
+        mesh_vertex(direction * radius, direction, Vec2::new(u, v))
+    };
+
+    let mut vertices = Vec::with_capacity(segments * (rings - 1) * 6);
+    for ring in 0..rings {
+        let (v0, v1) = (ring as f32 / rings as f32, (ring + 1) as f32 / rings as f32);
+        for segment in 0..segments {
+            let (u0, u1) = (segment as f32 / segments as f32, (segment + 1) as f32 / segments as f32);
+            // Pole vertices take the middle of their segment's longitude
+            let middle = (u0 + u1) / 2.0;
+            if ring == 0 {
+                vertices.extend([point(middle, v0), point(u1, v1), point(u0, v1)]);
+            } else if ring == rings - 1 {
+                vertices.extend([point(u0, v0), point(u1, v0), point(middle, v1)]);
+            } else {
+                vertices.extend([point(u0, v0), point(u1, v0), point(u1, v1)]);
+                vertices.extend([point(u0, v0), point(u1, v1), point(u0, v1)]);
+            }
+        }
+    }
+    vertices
+}
+
+// Sphere made by splitting every face of an icosahedron into four
+// `subdivisions` times, which spreads triangles far more evenly than uv_sphere
+pub fn icosphere(radius: f32, subdivisions: usize) -> Vec<Vertex> {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points: Vec<Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ].iter().map(|&(x, y, z)| Vec3::new(x, y, z).normalize()).collect();
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges shared by two faces get a single midpoint
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, points: &mut Vec<Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(((points[a] + points[b]) / 2.0).normalize());
+                points.len() - 1
+            })
+        };
+        faces = faces.iter().flat_map(|&[a, b, c]| {
+            let ab = midpoint(a, b, &mut points);
+            let bc = midpoint(b, c, &mut points);
+            let ca = midpoint(c, a, &mut points);
+            [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+
+    let mut vertices = Vec::with_capacity(faces.len() * 3);
+    for [a, b, c] in faces {
+        let [a, b, c] = [a, b, c].map(|i| mesh_vertex(points[i] * radius, points[i], Vec2::zeros()));
+        let outward = a.position + b.position + c.position;
+        push_facing(&mut vertices, a, b, c, &outward);
+    }
+    set_spherical_tex_coords(&mut vertices);
+    vertices
+}
+
+// Flat ring in the y = 0 plane facing +y, for planetary rings. Only the top
+// side exists, so draw it with CullMode::None or pass it through
+// double_sided. u runs from the inner edge (0)
+// to the outer edge (1) and v once around the ring.
+pub fn annulus(inner_radius: f32, outer_radius: f32, segments: usize) -> Vec<Vertex> {
+    let segments = segments.max(3);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let point = |radius: f32, u: f32, v: f32| {
+        let angle = v * 2.0 * PI;
+        mesh_vertex(Vec3::new(angle.cos() * radius, 0.0, angle.sin() * radius), up, Vec2::new(u, v))
+    };
+
+    let mut vertices = Vec::with_capacity(segments * 6);
+    for segment in 0..segments {
+        let (v0, v1) = (segment as f32 / segments as f32, (segment + 1) as f32 / segments as f32);
+        let (inner0, outer0) = (point(inner_radius, 0.0, v0), point(outer_radius, 1.0, v0));
+        let (inner1, outer1) = (point(inner_radius, 0.0, v1), point(outer_radius, 1.0, v1));
+        push_facing(&mut vertices, inner0.clone(), outer0, outer1.clone(), &up);
+        push_facing(&mut vertices, inner0, outer1, inner1, &up);
+    }
+    vertices
+}
+
+// Torus around the y axis. `major_radius` is the distance from the origin to
+// the center of the tube and `minor_radius` the tube's radius; u runs around
+// the y axis and v around the tube.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: usize, minor_segments: usize) -> Vec<Vertex> {
+    let (major_segments, minor_segments) = (major_segments.max(3), minor_segments.max(3));
+    let point = |u: f32, v: f32| {
+        let (around, tube) = (u * 2.0 * PI, v * 2.0 * PI);
+        let center = Vec3::new(around.cos(), 0.0, around.sin()) * major_radius;
+        let normal = Vec3::new(around.cos() * tube.cos(), tube.sin(), around.sin() * tube.cos());
+        mesh_vertex(center + normal * minor_radius, normal, Vec2::new(u, v))
+    };
+
+    let mut vertices = Vec::with_capacity(major_segments * minor_segments * 6);
+    for i in 0..major_segments {
+        let (u0, u1) = (i as f32 / major_segments as f32, (i + 1) as f32 / major_segments as f32);
+        for j in 0..minor_segments {
+            let (v0, v1) = (j as f32 / minor_segments as f32, (j + 1) as f32 / minor_segments as f32);
+            let corners = [point(u0, v0), point(u1, v0), point(u1, v1), point(u0, v1)];
+            let outward = corners.iter().map(|corner| corner.normal).sum::<Vec3>();
+            let [a, b, c, d] = corners;
+            push_facing(&mut vertices, a.clone(), b, c.clone(), &outward);
+            push_facing(&mut vertices, a, c, d, &outward);
+        }
+    }
+    vertices
+}
+
+// `vertices` followed by a back side: every triangle again, wound the other
+// way with its normals flipped, so open surfaces survive back-face culling
+pub fn double_sided(vertices: &[Vertex]) -> Vec<Vertex> {
+    let mut both = vertices.to_vec();
+    for triangle in vertices.chunks_exact(3) {
+        for vertex in [&triangle[0], &triangle[2], &triangle[1]] {
+            both.push(Vertex { normal: -vertex.normal, ..vertex.clone() });
+        }
+    }
+    both
+}
+
+// A copy of `vertices` moved by `offset`, for combining meshes
+pub fn translated(vertices: &[Vertex], offset: Vec3) -> Vec<Vertex> {
+    vertices.iter()
+        .map(|vertex| mesh_vertex(vertex.position + offset, vertex.normal, vertex.tex_coords))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every triangle is non-degenerate and wound counter-clockwise around its
+    // vertex normals, which are unit length
+    fn assert_well_formed(name: &str, vertices: &[Vertex]) {
+        assert_eq!(vertices.len() % 3, 0);
+        for triangle in vertices.chunks(3) {
+            let face = (triangle[1].position - triangle[0].position).cross(&(triangle[2].position - triangle[0].position));
+            assert!(face.magnitude() > 1e-7, "{} has a degenerate triangle", name);
+            let normal: Vec3 = triangle.iter().map(|vertex| vertex.normal).sum();
+            assert!(face.dot(&normal) > 0.0, "{} has a triangle facing inward", name);
+            for vertex in triangle {
+                assert!((vertex.normal.magnitude() - 1.0).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn spheres_are_closed_and_outward_facing() {
+        let sphere = uv_sphere(0.5, 16, 8);
+        assert_well_formed("uv sphere", &sphere);
+        // Pole rings have one triangle per segment, the others two
+        assert_eq!(sphere.len(), (16 * 2 + 16 * 6 * 2) * 3);
+        for vertex in &sphere {
+            assert!((vertex.position.magnitude() - 0.5).abs() < 1e-5);
+        }
+
+        let ico = icosphere(2.0, 2);
+        assert_well_formed("icosphere", &ico);
+        assert_eq!(ico.len(), 20 * 16 * 3);
+        for vertex in &ico {
+            assert!((vertex.position.magnitude() - 2.0).abs() < 1e-4);
+            assert!((vertex.position / 2.0 - vertex.normal).magnitude() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn ring_and_torus_are_well_formed() {
+        let ring = annulus(0.7, 1.0, 32);
+        assert_well_formed("annulus", &ring);
+        for vertex in &ring {
+            let radius = vertex.position.xz().magnitude();
+            assert!((radius - (0.7 + 0.3 * vertex.tex_coords.x)).abs() < 1e-5);
+        }
+
+        let both = double_sided(&ring);
+        assert_well_formed("double-sided annulus", &both);
+        assert_eq!(both.len(), ring.len() * 2);
+        assert!(both[ring.len()..].iter().all(|vertex| vertex.normal.y == -1.0));
+
+        let torus = torus(1.0, 0.25, 24, 12);
+        assert_well_formed("torus", &torus);
+        assert_eq!(torus.len(), 24 * 12 * 6);
+        for vertex in &torus {
+            let center = Vec3::new(vertex.position.x, 0.0, vertex.position.z).normalize();
+            assert!(((vertex.position - center).magnitude() - 0.25).abs() < 1e-4);
+        }
+    }
+}
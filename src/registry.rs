@@ -0,0 +1,175 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use fastnoise_lite::FastNoiseLite;
+use crate::bake::{baked_map_path, baked_program};
+use crate::obj::SPHERE_RADIUS;
+use crate::planet_definition::{load_planet_directory, PlanetFileError};
+use crate::planet_graphs;
+use crate::planet_noise;
+use crate::texture::{Texture, TextureShader};
+use crate::shader::{
+    earth_shader, jupiter_shader, mercury_shader, moon_body_shader, neptune_shader, saturn_shader, sun_shader,
+    venus_shader, vertex_shader, FragmentShader, PlanetShader, VertexShader, EARTH_RELIEF, JUPITER_LIGHTING,
+    MERCURY_LIGHTING, MERCURY_RELIEF, MOON_RELIEF, SATURN_LIGHTING, SUN_LIGHTING, VENUS_LIGHTING,
+};
+
+pub type NoisePreset = Arc<dyn Fn() -> FastNoiseLite + Send + Sync>;
+
+// A vertex/fragment shader pair plus the noise its fragment shader samples
+#[derive(Clone)]
+pub struct ShaderProgram {
+    pub name: String,
+    pub vertex: Arc<dyn VertexShader>,
+    pub fragment: Arc<dyn FragmentShader>,
+    pub noise: NoisePreset,
+}
+
+impl ShaderProgram {
+    pub fn new<F, N>(name: &str, fragment: F, noise: N) -> Self
+    where
+        F: FragmentShader + 'static,
+        N: Fn() -> FastNoiseLite + Send + Sync + 'static,
+    {
+        ShaderProgram {
+            name: name.to_string(),
+            vertex: Arc::new(vertex_shader),
+            fragment: Arc::new(fragment),
+            noise: Arc::new(noise),
+        }
+    }
+
+    pub fn with_vertex_shader<V: VertexShader + 'static>(mut self, vertex: V) -> Self {
+        self.vertex = Arc::new(vertex);
+        self
+    }
+
+    pub fn create_noise(&self) -> FastNoiseLite {
+        (self.noise)()
+    }
+}
+
+// Shader programs by name, kept in registration order
+#[derive(Clone, Default)]
+pub struct ShaderRegistry {
+    programs: Vec<ShaderProgram>,
+}
+
+impl ShaderRegistry {
+    pub fn new() -> Self {
+        ShaderRegistry { programs: Vec::new() }
+    }
+
+    // The sun, the six planets and the moon, in the order of the viewer's number keys
+    pub fn with_planets() -> Self {
+        let mut registry = ShaderRegistry::new();
+        let sun = PlanetShader::new(sun_shader).with_lighting(SUN_LIGHTING);
+        let mercury = PlanetShader::new(mercury_shader).with_lighting(MERCURY_LIGHTING).with_displacement(MERCURY_RELIEF);
+        let venus = PlanetShader::new(venus_shader).with_lighting(VENUS_LIGHTING);
+        let earth = PlanetShader::new(earth_shader).with_displacement(EARTH_RELIEF);
+        let jupiter = PlanetShader::new(jupiter_shader).with_lighting(JUPITER_LIGHTING);
+        let saturn = PlanetShader::new(saturn_shader).with_lighting(SATURN_LIGHTING);
+        let moon = PlanetShader::new(moon_body_shader).with_displacement(MOON_RELIEF);
+        registry.register(ShaderProgram::new("sun", sun, planet_noise::get_sun_noise));
+        registry.register(ShaderProgram::new("mercury", mercury, planet_noise::get_mercury_noise));
+        registry.register(ShaderProgram::new("venus", venus, planet_noise::get_venus_noise));
+        registry.register(ShaderProgram::new("earth", earth, planet_noise::get_earth_noise));
+        registry.register(ShaderProgram::new("jupiter", jupiter, planet_noise::get_jupiter_noise));
+        registry.register(ShaderProgram::new("saturn", saturn, planet_noise::get_saturn_noise));
+        registry.register(ShaderProgram::new("neptune", neptune_shader, planet_noise::get_neptune_noise));
+        registry.register(ShaderProgram::new("moon", moon, planet_noise::get_earth_noise));
+        registry
+    }
+
+    // The same planets with their shaders rebuilt as node graphs
+    pub fn with_planet_graphs() -> Self {
+        let mut registry = ShaderRegistry::new();
+        registry.register(ShaderProgram::new("sun", planet_graphs::sun_graph(), planet_noise::get_sun_noise));
+        registry.register(ShaderProgram::new("mercury", planet_graphs::mercury_graph(), planet_noise::get_mercury_noise));
+        registry.register(ShaderProgram::new("venus", planet_graphs::venus_graph(), planet_noise::get_venus_noise));
+        registry.register(ShaderProgram::new("earth", planet_graphs::earth_graph(), planet_noise::get_earth_noise));
+        registry.register(ShaderProgram::new("jupiter", planet_graphs::jupiter_graph(), planet_noise::get_jupiter_noise));
+        registry.register(ShaderProgram::new("saturn", planet_graphs::saturn_graph(), planet_noise::get_saturn_noise));
+        registry.register(ShaderProgram::new("neptune", planet_graphs::neptune_graph(), planet_noise::get_neptune_noise));
+        registry.register(ShaderProgram::new("moon", planet_graphs::moon_graph(), planet_noise::get_earth_noise));
+        registry
+    }
+
+    // Adds every planet description file in `directory` that loads and
+    // returns the errors of the rest
+    pub fn register_directory(&mut self, directory: &Path) -> Vec<PlanetFileError> {
+        let (definitions, errors) = load_planet_directory(directory);
+        for definition in definitions {
+            self.register(definition.into_program());
+        }
+        errors
+    }
+
+    // Adds every .png or .ppm image in `directory` as a texture-mapped planet
+    // named after its file
+    pub fn register_textures(&mut self, directory: &Path) -> io::Result<()> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "png" || extension == "ppm") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let texture = Arc::new(Texture::load(&path)?);
+            let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("texture");
+            self.register(ShaderProgram::new(name, TextureShader::new(texture), FastNoiseLite::new));
+        }
+        Ok(())
+    }
+
+    // Swaps in the baked map from `directory` for every program that has one
+    // (see bake.rs) and returns how many were replaced
+    pub fn use_baked_maps(&mut self, directory: &Path) -> io::Result<usize> {
+        let mut replaced = 0;
+        for program in self.programs.iter_mut() {
+            let path = baked_map_path(directory, &program.name);
+            if path.is_file() {
+                *program = baked_program(program, Texture::load(&path)?, SPHERE_RADIUS);
+                replaced += 1;
+            }
+        }
+        Ok(replaced)
+    }
+
+    // Registering a name that already exists replaces it in place
+    pub fn register(&mut self, program: ShaderProgram) {
+        match self.programs.iter_mut().find(|existing| existing.name == program.name) {
+            Some(existing) => *existing = program,
+            None => self.programs.push(program),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<ShaderProgram> {
+        let index = self.programs.iter().position(|program| program.name == name)?;
+        Some(self.programs.remove(index))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ShaderProgram> {
+        self.programs.iter().find(|program| program.name == name)
+    }
+
+    pub fn get_index(&self, index: usize) -> Option<&ShaderProgram> {
+        self.programs.get(index)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.programs.iter().map(|program| program.name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+}
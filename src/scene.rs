@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+
+use crate::lod::LodMesh;
+use crate::matrix::create_model_matrix;
+use crate::mesh::Mesh;
+use crate::registry::ShaderProgram;
+use crate::screen::framebuffer::Framebuffer;
+use crate::uniforms::{render, CullMode, RenderStats, Uniforms};
+
+// Local placement of a node relative to its parent
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Vec3,
+    pub scale: f32,
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, scale: f32, rotation: Vec3) -> Self {
+        Transform { translation, rotation, scale }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        create_model_matrix(self.translation, self.scale, self.rotation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Vec3::new(0.0, 0.0, 0.0))
+    }
+}
+
+// A node draws its mesh with its program (when it has both) and passes its
+// world matrix down to its children. Group nodes have neither. Nodes with
+// LODs get their mesh picked from them by lod::update_lods.
+#[derive(Clone)]
+pub struct SceneNode {
+    pub name: String,
+    pub transform: Transform,
+    pub mesh: Option<Arc<Mesh>>,
+    pub program: Option<ShaderProgram>,
+    pub cull_mode: Option<CullMode>,
+    pub lods: Option<Arc<LodMesh>>,
+    pub lod_level: usize,
+    pub children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    pub fn new(name: &str) -> Self {
+        SceneNode {
+            name: name.to_string(),
+            transform: Transform::default(),
+            mesh: None,
+            program: None,
+            cull_mode: None,
+            lods: None,
+            lod_level: 0,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_mesh(mut self, mesh: Arc<Mesh>, program: ShaderProgram) -> Self {
+        self.mesh = Some(mesh);
+        self.program = Some(program);
+        self
+    }
+
+    // Starts at the finest level until update_lods runs
+    pub fn with_lods(mut self, lods: Arc<LodMesh>, program: ShaderProgram) -> Self {
+        self.mesh = Some(lods.level(0).mesh.clone());
+        self.program = Some(program);
+        self.lods = Some(lods);
+        self.lod_level = 0;
+        self
+    }
+
+    // Overrides the frame's cull mode, e.g. for single-sided rings
+    pub fn with_cull_mode(mut self, cull_mode: CullMode) -> Self {
+        self.cull_mode = Some(cull_mode);
+        self
+    }
+
+    pub fn with_child(mut self, child: SceneNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    // Depth-first search by name
+    pub fn find(&self, name: &str) -> Option<&SceneNode> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(name))
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut SceneNode> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter_mut().find_map(|child| child.find_mut(name))
+    }
+
+    // World-space position of a node's origin, if it is in this tree
+    pub fn world_position(&self, name: &str) -> Option<Vec3> {
+        world_matrix(self, name, Mat4::identity()).map(|matrix| {
+            let origin = matrix * Vec4::new(0.0, 0.0, 0.0, 1.0);
+            Vec3::new(origin.x, origin.y, origin.z)
+        })
+    }
+}
+
+fn world_matrix(node: &SceneNode, name: &str, parent: Mat4) -> Option<Mat4> {
+    let world = parent * node.transform.matrix();
+    if node.name == name {
+        return Some(world);
+    }
+    node.children.iter().find_map(|child| world_matrix(child, name, world))
+}
+
+// Where the scene's light comes from. A point light is turned into a
+// per-object direction from the object's origin, keeping the strength of
+// the frame's light_dir.
+#[derive(Debug, Clone, Copy)]
+pub enum SceneLight {
+    Directional,
+    Point(Vec3),
+}
+
+// Draws every node of the tree into one framebuffer, so all objects share
+// its depth buffer. `frame` supplies the camera, light and time; its model
+// matrix and noise are replaced per node.
+pub fn render_scene(framebuffer: &mut Framebuffer, root: &SceneNode, frame: &Uniforms, light: SceneLight) -> RenderStats {
+    let mut stats = RenderStats::default();
+    render_node(framebuffer, root, frame, light, Mat4::identity(), &mut stats);
+    stats
+}
+
+fn render_node(framebuffer: &mut Framebuffer, node: &SceneNode, frame: &Uniforms, light: SceneLight, parent: Mat4, stats: &mut RenderStats) {
+    let world = parent * node.transform.matrix();
+
+    if let (Some(mesh), Some(program)) = (&node.mesh, &node.program) {
+        let light_dir = match light {
+            SceneLight::Directional => frame.light_dir,
+            SceneLight::Point(position) => {
+                let origin = world * Vec4::new(0.0, 0.0, 0.0, 1.0);
+                let to_light = position - Vec3::new(origin.x, origin.y, origin.z);
+                if to_light.magnitude() > 0.0 {
+                    to_light.normalize() * frame.light_dir.magnitude()
+                } else {
+                    frame.light_dir
+                }
+            }
+        };
+        let uniforms = Uniforms {
+            model_matrix: world,
+            view_matrix: frame.view_matrix,
+            projection_matrix: frame.projection_matrix,
+            viewport_matrix: frame.viewport_matrix,
+            light_dir,
+            time: frame.time,
+            noise: program.create_noise(),
+            cull_mode: node.cull_mode.unwrap_or(frame.cull_mode),
+            front_face: frame.front_face,
+        };
+        *stats += render(framebuffer, &uniforms, mesh, program);
+    }
+
+    for child in &node.children {
+        render_node(framebuffer, child, frame, light, world, stats);
+    }
+}
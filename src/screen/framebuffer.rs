@@ -44,12 +44,44 @@ impl Framebuffer {
             }
         }
     }
+    // True when a fragment at `depth` would be kept by `point`
+    pub fn depth_test(&self, x: usize, y: usize, depth: f32) -> bool {
+        x < self.width && y < self.height && self.zbuffer[y*self.width + x] > depth
+    }
+
+    // Copies a rectangle (color and depth) into a standalone framebuffer
+    pub fn read_region(&self, x: usize, y: usize, width: usize, height: usize) -> Framebuffer {
+        let mut region = Framebuffer::new(width, height);
+        region.background_color = self.background_color;
+        region.current_color = self.current_color;
+        for row in 0..height {
+            let src = (y + row) * self.width + x;
+            let dst = row * width;
+            region.buffer[dst..dst + width].copy_from_slice(&self.buffer[src..src + width]);
+            region.zbuffer[dst..dst + width].copy_from_slice(&self.zbuffer[src..src + width]);
+        }
+        region
+    }
+
+    pub fn write_region(&mut self, x: usize, y: usize, region: &Framebuffer) {
+        for row in 0..region.height {
+            let src = row * region.width;
+            let dst = (y + row) * self.width + x;
+            self.buffer[dst..dst + region.width].copy_from_slice(&region.buffer[src..src + region.width]);
+            self.zbuffer[dst..dst + region.width].copy_from_slice(&region.zbuffer[src..src + region.width]);
+        }
+    }
+
     pub fn color_array_to_u32(&mut self) -> Vec<u32> {
         self.buffer.iter().map(|color| {
             ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
         }).collect()  // Collect into a Vec<u32>
     }
 
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.buffer.iter().flat_map(|color| [color.r, color.g, color.b]).collect()
+    }
+
     pub fn set_bgcolor(&mut self, color: u32){
         self.background_color = Color::from_hex(color);
     }
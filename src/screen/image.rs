@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::screen::color::Color;
+use crate::screen::framebuffer::Framebuffer;
+
+pub fn save_ppm(framebuffer: &Framebuffer, path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "P6\n{} {}\n255\n", framebuffer.width, framebuffer.height)?;
+    writer.write_all(&framebuffer.to_rgb_bytes())?;
+    writer.flush()
+}
+
+pub fn save_png(framebuffer: &Framebuffer, path: &Path) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, framebuffer.width as u32, framebuffer.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&framebuffer.to_rgb_bytes()).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+// Decodes any 8-bit PNG into a framebuffer; alpha is dropped
+pub fn load_png(path: &Path) -> io::Result<Framebuffer> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(io::Error::other)?;
+
+    let channels = info.color_type.samples();
+    let mut framebuffer = Framebuffer::new(info.width as usize, info.height as usize);
+    for (pixel, color) in data.chunks(channels).zip(framebuffer.buffer.iter_mut()) {
+        *color = match channels {
+            1 | 2 => Color::new(pixel[0] as i32, pixel[0] as i32, pixel[0] as i32),
+            _ => Color::new(pixel[0] as i32, pixel[1] as i32, pixel[2] as i32),
+        };
+    }
+    Ok(framebuffer)
+}
+
+// Decodes binary (P6) and ASCII (P3) PPM files with up to 16 bits per channel
+pub fn load_ppm(path: &Path) -> io::Result<Framebuffer> {
+    let mut data = Vec::new();
+    BufReader::new(File::open(path)?).read_to_end(&mut data)?;
+    decode_ppm(&data).map_err(|message| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+    })
+}
+
+// Headers claiming larger images are rejected before anything is allocated
+const MAX_PPM_SIDE: usize = 16384;
+
+fn decode_ppm(data: &[u8]) -> Result<Framebuffer, String> {
+    let mut position = 0;
+    let magic = ppm_token(data, &mut position).ok_or("missing PPM header")?;
+    let binary = match magic {
+        "P6" => true,
+        "P3" => false,
+        _ => return Err(format!("unsupported PPM type {}", magic)),
+    };
+    let mut header = [0usize; 3];
+    for value in header.iter_mut() {
+        let token = ppm_token(data, &mut position).ok_or("truncated PPM header")?;
+        *value = token.parse().map_err(|_| format!("invalid PPM header value {}", token))?;
+    }
+    let [width, height, max_value] = header;
+    if max_value == 0 || max_value > 65535 {
+        return Err(format!("invalid PPM maximum value {}", max_value));
+    }
+
+    if width > MAX_PPM_SIDE || height > MAX_PPM_SIDE {
+        return Err(format!("PPM image {}x{} is larger than {}x{}", width, height, MAX_PPM_SIDE, MAX_PPM_SIDE));
+    }
+
+    let count = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(3)).ok_or("PPM image is too large")?;
+    let samples: Vec<usize> = if binary {
+        // Exactly one whitespace byte separates the header from the pixels
+        let start = position + 1;
+        let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+        let end = count.checked_mul(bytes_per_sample).and_then(|length| start.checked_add(length));
+        let pixels = end.and_then(|end| data.get(start..end)).ok_or("truncated PPM pixel data")?;
+        if bytes_per_sample == 1 {
+            pixels.iter().map(|&byte| byte as usize).collect()
+        } else {
+            pixels.chunks(2).map(|pair| (pair[0] as usize) << 8 | pair[1] as usize).collect()
+        }
+    } else {
+        // Every ASCII sample takes at least a digit and a separator
+        if count > (data.len() - position) / 2 + 1 {
+            return Err("truncated PPM pixel data".to_string());
+        }
+        (0..count)
+            .map(|_| {
+                let token = ppm_token(data, &mut position).ok_or("truncated PPM pixel data")?;
+                token.parse().map_err(|_| format!("invalid PPM sample {}", token))
+            })
+            .collect::<Result<_, String>>()?
+    };
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    for (pixel, color) in samples.chunks(3).zip(framebuffer.buffer.iter_mut()) {
+        let scale = |sample: usize| (sample.min(max_value) * 255 / max_value) as i32;
+        *color = Color::new(scale(pixel[0]), scale(pixel[1]), scale(pixel[2]));
+    }
+    Ok(framebuffer)
+}
+
+// Next whitespace-separated token, skipping `#` comments
+fn ppm_token<'a>(data: &'a [u8], position: &mut usize) -> Option<&'a str> {
+    loop {
+        while *position < data.len() && data[*position].is_ascii_whitespace() {
+            *position += 1;
+        }
+        if data.get(*position) != Some(&b'#') {
+            break;
+        }
+        while *position < data.len() && data[*position] != b'\n' {
+            *position += 1;
+        }
+    }
+    let start = *position;
+    while *position < data.len() && !data[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+    if start == *position {
+        return None;
+    }
+    std::str::from_utf8(&data[start..*position]).ok()
+}
+
+// Picks the decoder from the file extension (.png or .ppm)
+pub fn load_image(path: &Path) -> io::Result<Framebuffer> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => load_png(path),
+        Some("ppm") => load_ppm(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported image format: {}", path.display()),
+        )),
+    }
+}
+
+// Picks the format from the file extension (.png or .ppm)
+pub fn save_image(framebuffer: &Framebuffer, path: &Path) -> io::Result<()> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => save_png(framebuffer, path),
+        Some("ppm") => save_ppm(framebuffer, path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported image format: {}", path.display()),
+        )),
+    }
+}
+
+// Encodes an animation; each frame gets its own NeuQuant-quantized 256 color palette
+pub fn save_gif(frames: &[Framebuffer], path: &Path, delay_ms: u32) -> io::Result<()> {
+    let first = frames.first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no frames to encode"))?;
+    let (width, height) = match (u16::try_from(first.width), u16::try_from(first.height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}x{} frames are too large for a GIF, which is at most 65535x65535", first.width, first.height),
+        )),
+    };
+
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(writer, width, height, &[]).map_err(io::Error::other)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+
+    for framebuffer in frames {
+        let mut frame = gif::Frame::from_rgb_speed(width, height, &framebuffer.to_rgb_bytes(), GIF_QUANTIZE_SPEED);
+        // GIF delays are in hundredths of a second
+        frame.delay = (delay_ms / 10) as u16;
+        encoder.write_frame(&frame).map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+// 1 is the slowest and best quality, 30 the fastest
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+// `out/earth.png` becomes `out/earth_0007.png` for frame 7
+pub fn numbered_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("frame");
+    let mut name = format!("{}_{:04}", stem, index);
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        name = format!("{}.{}", name, extension);
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_binary_and_ascii_ppm() {
+        let mut binary = b"P6\n# comment\n2 1\n255\n".to_vec();
+        binary.extend_from_slice(&[255, 0, 0, 0, 128, 255]);
+        let framebuffer = decode_ppm(&binary).unwrap();
+        assert_eq!((framebuffer.width, framebuffer.height), (2, 1));
+        assert_eq!(framebuffer.buffer[1].to_hex(), 0x0080ff);
+
+        let ascii = decode_ppm(b"P3 1 1 15\n15 0 5\n").unwrap();
+        assert_eq!(ascii.buffer[0].to_hex(), 0xff0055);
+
+        assert!(decode_ppm(b"P6 2 2 255\n\x00\x00").is_err());
+    }
+
+    #[test]
+    fn ppm_rejects_sizes_it_cannot_hold() {
+        assert!(decode_ppm(b"P6 4294967296 4294967296 255\n").is_err());
+        assert!(decode_ppm(b"P6 18446744073709551615 3 255\n").is_err());
+        assert!(decode_ppm(b"P3 16384 16384 255\n1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn gif_rejects_frames_wider_than_it_can_hold() {
+        let path = std::env::temp_dir().join(format!("too_wide_{}.gif", std::process::id()));
+        let error = save_gif(&[Framebuffer::new(65536, 1)], &path, 40).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
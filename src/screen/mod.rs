@@ -1,2 +1,4 @@
 pub mod color;
-pub mod framebuffer;
\ No newline at end of file
+pub mod framebuffer;
+pub mod image;
+pub mod text;
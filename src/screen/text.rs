@@ -0,0 +1,141 @@
+use crate::screen::color::Color;
+use crate::screen::framebuffer::Framebuffer;
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+// Glyph plus one pixel of spacing on each axis
+const ADVANCE_X: usize = GLYPH_WIDTH + 1;
+const ADVANCE_Y: usize = GLYPH_HEIGHT + 2;
+
+// 5x7 font for printable ASCII (' ' to '~'), one byte per column with the
+// top row in bit 0
+const FONT: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x32], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x00, 0x7F, 0x10, 0x28, 0x44], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x02, 0x01, 0x02, 0x04, 0x02],
+];
+
+// Characters outside printable ASCII are drawn as '?'
+fn glyph(character: char) -> &'static [u8; GLYPH_WIDTH] {
+    let code = character as usize;
+    if (32..127).contains(&code) {
+        &FONT[code - 32]
+    } else {
+        &FONT['?' as usize - 32]
+    }
+}
+
+// Splits `text` into lines no wider than `max_width` pixels, breaking at
+// spaces where possible and mid-word otherwise
+pub fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
+    let max_chars = (max_width / ADVANCE_X).max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let needed = if line.is_empty() { word.len() } else { line.len() + 1 + word.len() };
+            if needed > max_chars && !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+            while line.chars().count() > max_chars {
+                let rest: String = line.chars().skip(max_chars).collect();
+                lines.push(line.chars().take(max_chars).collect());
+                line = rest;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// Draws text over whatever is in the framebuffer, ignoring the depth buffer.
+// Pixels outside the framebuffer are skipped.
+pub fn draw_text(framebuffer: &mut Framebuffer, x: usize, y: usize, text: &str, color: Color) {
+    for (row, line) in text.lines().enumerate() {
+        for (column, character) in line.chars().enumerate() {
+            let origin_x = x + column * ADVANCE_X;
+            let origin_y = y + row * ADVANCE_Y;
+            for (dx, bits) in glyph(character).iter().enumerate() {
+                for dy in 0..GLYPH_HEIGHT {
+                    let (px, py) = (origin_x + dx, origin_y + dy);
+                    if bits >> dy & 1 == 1 && px < framebuffer.width && py < framebuffer.height {
+                        framebuffer.buffer[py * framebuffer.width + px] = color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A wrapped message on a dark box, for errors shown in the viewer.
+// Returns the height of the box.
+pub fn draw_message_box(framebuffer: &mut Framebuffer, x: usize, y: usize, max_width: usize, text: &str, color: Color) -> usize {
+    let lines = wrap_text(text, max_width.saturating_sub(4));
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) * ADVANCE_X + 4;
+    let height = lines.len() * ADVANCE_Y + 2;
+    let background = Color::new(20, 0, 0);
+    for py in y..(y + height).min(framebuffer.height) {
+        for px in x..(x + width).min(framebuffer.width) {
+            framebuffer.buffer[py * framebuffer.width + px] = background;
+        }
+    }
+    draw_text(framebuffer, x + 2, y + 2, &lines.join("\n"), color);
+    height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_spaces_and_splits_long_words() {
+        let width = 10 * ADVANCE_X;
+        assert_eq!(wrap_text("hello world again", width), vec!["hello", "world", "again"]);
+        assert_eq!(wrap_text("abcdefghijklmno", width), vec!["abcdefghij", "klmno"]);
+        assert_eq!(wrap_text("a b\nc", width), vec!["a b", "c"]);
+    }
+
+    #[test]
+    fn draws_inside_bounds_only() {
+        let mut framebuffer = Framebuffer::new(8, 8);
+        let white = Color::new(255, 255, 255);
+        draw_text(&mut framebuffer, 4, 0, "I", white);
+        // The I's vertical bar is its middle column
+        assert_eq!(framebuffer.buffer[3 * 8 + 6].r, 255);
+        assert_eq!(framebuffer.buffer[3 * 8 + 5].r, 0);
+        draw_text(&mut framebuffer, 6, 6, "W", white);
+    }
+}
@@ -1,14 +1,134 @@
 use std::f32::consts::PI;
-use std::ops::Add;
-use std::os::unix::raw::gid_t;
 
-use nalgebra_glm::{Mat3, Mat4, Vec3, Vec4};
-use rand::{Rng, SeedableRng};
+use fastnoise_lite::FastNoiseLite;
+use nalgebra_glm::{Mat3, Vec3, Vec4};
+use crate::displacement::{Displacement, Relief};
 use crate::fragments::Fragment;
-use crate::screen::color::{self, Color};
+use crate::screen::color::Color;
 use crate::uniforms::Uniforms;
 use crate::vertex::Vertex;
-use crate::rand::rngs::StdRng;
+
+// Model-space units are scaled up so the noise zoom factors keep roughly the
+// feature size they had when the shaders sampled screen pixels.
+pub(crate) const SURFACE_SCALE: f32 = 400.0;
+
+pub trait VertexShader: Send + Sync {
+    fn shade(&self, vertex: &Vertex, uniforms: &Uniforms) -> Vertex;
+}
+
+pub trait FragmentShader: Send + Sync {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color;
+
+    // The clamp shade applies to fragment.intensity
+    fn lighting(&self) -> LightingClamp {
+        LightingClamp::DEFAULT
+    }
+
+    // How the exporter raises the planet's surface from its noise
+    fn displacement(&self) -> Displacement {
+        Displacement::default()
+    }
+
+    // Whether shade reads Fragment::tex_coords_dx/dy; they are left at zero
+    // for shaders that don't, which saves two interpolations per fragment
+    fn needs_derivatives(&self) -> bool {
+        false
+    }
+}
+
+// Plain functions such as `vertex_shader` or `earth_shader` work as shaders directly
+impl<F> VertexShader for F
+where
+    F: Fn(&Vertex, &Uniforms) -> Vertex + Send + Sync,
+{
+    fn shade(&self, vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
+        self(vertex, uniforms)
+    }
+}
+
+impl<F> FragmentShader for F
+where
+    F: Fn(&Fragment, &Uniforms) -> Color + Send + Sync,
+{
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        self(fragment, uniforms)
+    }
+}
+
+// A shader's colors are scaled by the fragment's intensity clamped to
+// min..max. Baking evaluates the shader at intensity `reference` and relights
+// the map by clamp(intensity) / reference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightingClamp {
+    pub min: f32,
+    pub max: f32,
+    pub reference: f32,
+}
+
+impl LightingClamp {
+    pub const DEFAULT: LightingClamp = LightingClamp::new(0.05, 2.0);
+    pub const UNLIT: LightingClamp = LightingClamp::new(1.0, 1.0);
+
+    pub const fn new(min: f32, max: f32) -> Self {
+        LightingClamp { min, max, reference: 1.0 }
+    }
+
+    pub fn clamp(&self, intensity: f32) -> f32 {
+        intensity.clamp(self.min, self.max)
+    }
+
+    pub fn factor(&self, intensity: f32) -> f32 {
+        self.clamp(intensity) / self.reference
+    }
+}
+
+// A shader function together with the lighting clamp and relief it
+// reports, for the planets that don't use the defaults
+pub struct PlanetShader<F> {
+    shade: F,
+    lighting: LightingClamp,
+    displacement: Displacement,
+}
+
+impl<F> PlanetShader<F> {
+    pub fn new(shade: F) -> Self {
+        PlanetShader { shade, lighting: LightingClamp::DEFAULT, displacement: Displacement::default() }
+    }
+
+    pub fn with_lighting(mut self, lighting: LightingClamp) -> Self {
+        self.lighting = lighting;
+        self
+    }
+
+    pub fn with_displacement(mut self, displacement: Displacement) -> Self {
+        self.displacement = displacement;
+        self
+    }
+}
+
+impl<F> FragmentShader for PlanetShader<F>
+where
+    F: Fn(&Fragment, &Uniforms) -> Color + Send + Sync,
+{
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        (self.shade)(fragment, uniforms)
+    }
+
+    fn lighting(&self) -> LightingClamp {
+        self.lighting
+    }
+
+    fn displacement(&self) -> Displacement {
+        self.displacement
+    }
+}
+
+pub(crate) const SUN_LIGHTING: LightingClamp = LightingClamp::UNLIT;
+// Mercury only caps the light, and is baked at that cap
+pub(crate) const MERCURY_LIGHTING: LightingClamp = LightingClamp { min: f32::MIN, max: 0.9, reference: 0.9 };
+pub(crate) const VENUS_LIGHTING: LightingClamp = LightingClamp::new(0.1, 1.5);
+pub(crate) const JUPITER_LIGHTING: LightingClamp = LightingClamp::new(0.05, 1.2);
+pub(crate) const SATURN_LIGHTING: LightingClamp = LightingClamp::new(0.1, 1.5);
 
 pub fn vertex_shader(
     vertex: &Vertex,
@@ -21,19 +141,8 @@ pub fn vertex_shader(
         vertex.position.z,
         1.0
     );
-    
-    let transformed = uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix * position;
-
-    // Perspective division
-    let w = transformed.w;
-    let ndc_position  = Vec4::new(
-        transformed.x/w,
-        transformed.y/w,
-        transformed.z/w,
-        1.0
-    );
 
-    let screen_position = uniforms.viewport_matrix * ndc_position;
+    let clip_position = uniforms.projection_matrix * uniforms.view_matrix * uniforms.model_matrix * position;
 
     // Transform normal
   let model_mat3 = Mat3::new(
@@ -41,193 +150,258 @@ pub fn vertex_shader(
     uniforms.model_matrix[4], uniforms.model_matrix[5], uniforms.model_matrix[6],
     uniforms.model_matrix[8], uniforms.model_matrix[9], uniforms.model_matrix[10]
   );
-  let normal_matrix = model_mat3.transpose().try_inverse().unwrap_or(Mat3::identity());
+  // Mat3::new takes rows, so model_mat3 is already the transpose and its
+  // inverse is the inverse-transpose normal matrix
+  let normal_matrix = model_mat3.try_inverse().unwrap_or(Mat3::identity());
 
   let transformed_normal = normal_matrix * vertex.normal;
-  
+
   Vertex {
     position: vertex.position,
     normal: vertex.normal,
     tex_coords: vertex.tex_coords,
     color: vertex.color,
-    transformed_position: Vec3::new(screen_position.x, screen_position.y, screen_position.z),
+    clip_position,
+    transformed_position: vertex.transformed_position,
     transformed_normal,
+    inv_w: vertex.inv_w,
   }
 }
 
+pub(crate) fn surface_point(fragment: &Fragment) -> Vec3 {
+    fragment.vertex_position * SURFACE_SCALE
+}
+
+// Interpolates between (position, color) stops sorted by position, keeping
+// the end colors past either end
+pub(crate) fn color_ramp(stops: &[(f32, Color)], value: f32) -> Color {
+    let (first, last) = (stops[0], stops[stops.len() - 1]);
+    if value <= first.0 {
+        return first.1;
+    }
+    for pair in stops.windows(2) {
+        let ((low, low_color), (high, high_color)) = (pair[0], pair[1]);
+        if value <= high {
+            let span = high - low;
+            let t = if span > 0.0 { (value - low) / span } else { 1.0 };
+            return low_color.lerp(&high_color, t);
+        }
+    }
+    last.1
+}
+
+// The noise zooms and thresholds below are shared with the node graph
+// versions in planet_graphs.rs and the exporter's relief in displacement.rs.
+
 // SUN
+pub(crate) const SUN_CELL_ZOOM: f32 = 5.0;
+// Cells below the first level are dark spots, above the second bright
+pub(crate) const SUN_DARK_LEVEL: f32 = 0.1;
+pub(crate) const SUN_BRIGHT_LEVEL: f32 = 0.55;
+
 pub fn sun_cellular_shader(fragment: &Fragment, uniforms: &Uniforms) ->Color{
-    let zoom = 5.0;
+    let zoom = SUN_CELL_ZOOM;
     let ox = 50.0 + uniforms.time as f32;
     let oy = 50.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
+    let p = surface_point(fragment);
 
-    let cell_noise_value = (uniforms.noise.get_noise_2d(
-       x*zoom +ox, 
-        y*zoom+oy
+    let cell_noise_value = (uniforms.noise.get_noise_3d(
+        p.x*zoom +ox,
+        p.y*zoom+oy,
+        p.z*zoom
     )+1.0)/2.0;
 
     let dark = Color::from_hex(0xff2a00);
     let normal = Color::from_hex(0xff5100);
     let white =Color::new(255,255, 255);
-    let final_color = if cell_noise_value< 0.1{
+    if cell_noise_value< SUN_DARK_LEVEL{
         dark*(cell_noise_value+0.5)
-    } else if cell_noise_value< 0.55{
+    } else if cell_noise_value< SUN_BRIGHT_LEVEL{
         normal*(cell_noise_value+0.4)
     } else{
         white*(cell_noise_value+0.2)
-    };
-    final_color
+    }
 }
 
 pub fn sun_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color{
     let spot_color = sun_cellular_shader(fragment, uniforms);
     let brighter_color = Color::from_hex(0xffe0ad);
-    let final_color = spot_color.blend_multiply(&brighter_color);
-    final_color
+    spot_color.blend_multiply(&brighter_color)
 }
 
 // EARTH
+// Continents, coastlines and biomes share this noise offset; the mixed
+// continent noise is land below EARTH_LAND_LEVEL and sea above it
+pub(crate) const EARTH_NOISE_OFFSET: f32 = 100.0;
+pub(crate) const EARTH_CONTINENT_ZOOM: f32 = 0.5;
+pub(crate) const EARTH_COAST_ZOOM: f32 = 8.0;
+pub(crate) const EARTH_LAND_LEVEL: f32 = 0.4;
+pub(crate) const EARTH_BIOME_ZOOM: f32 = 3.0;
+pub(crate) const EARTH_DESERT_LEVEL: f32 = 0.2;
+pub(crate) const EARTH_GREENERY_LEVEL: f32 = 0.8;
+pub(crate) const EARTH_CLOUD_ZOOM: f32 = 1.4;
+pub(crate) const EARTH_CLOUD_DETAIL_ZOOM: f32 = 5.0;
+pub(crate) const EARTH_CLOUD_LEVEL: f32 = 0.4;
+// Vertices of sphere_moon.obj further out than this belong to the moon
+pub(crate) const MOON_DISTANCE: f32 = 0.75;
+pub(crate) const MOON_ZOOM: f32 = 20.0;
+pub(crate) const EARTH_RELIEF: Displacement = Displacement { relief: Relief::Continents, amplitude: 0.03 };
+// The moon's surface is speckled noise without craters
+pub(crate) const MOON_RELIEF: Displacement = Displacement { relief: Relief::Hills { zoom: MOON_ZOOM }, amplitude: 0.03 };
+// Gray from half to full brightness as the noise goes from 0 to 1
+pub(crate) const MOON_RAMP: [(f32, Color); 2] = [(0.0, Color::from_hex(0x404040)), (1.0, Color::from_hex(0x808080))];
+
 pub fn earth_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color{
     let map_color = create_map(fragment, uniforms);
     let cloud = cloud_shader(fragment, uniforms);
     let final_color = map_color.blend_add(&cloud);
     let final_color = moon_shader(fragment, uniforms, final_color);
-    final_color*(fragment.intensity.min(2.0).max(0.05))
+    final_color*LightingClamp::DEFAULT.clamp(fragment.intensity)
 }
 
-fn moon_shader(fragment: &Fragment, uniforms: &Uniforms, color:Color)-> Color{
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let distance = ((x-680.0)*(x-680.0) + (y-250.0)*(y-250.0)).sqrt();
+// Standalone moon body, sampling the Earth's noise
+pub fn moon_body_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color{
+    moon_surface(fragment, uniforms)*LightingClamp::DEFAULT.clamp(fragment.intensity)
+}
 
-    if distance < 60.0 {
-        let t = uniforms.time as f32;
-        let noise = (uniforms.noise.get_noise_2d(
-            (x+t)*20.0,y*20.0)+1.0)/2.0;
-        Color::new(128, 128, 128)*(noise*0.5 +0.5)
+// The moon is part of sphere_moon.obj; its vertices are the only ones
+// far outside the planet's radius.
+fn moon_shader(fragment: &Fragment, uniforms: &Uniforms, color:Color)-> Color{
+    if fragment.vertex_position.magnitude() > MOON_DISTANCE {
+        moon_surface(fragment, uniforms)
     } else{
         color
     }
 }
+
+// The moon turns with its model matrix, so its surface is fixed in object space
+fn moon_surface(fragment: &Fragment, uniforms: &Uniforms)-> Color{
+    let p = surface_point(fragment);
+    let noise = (uniforms.noise.get_noise_3d(
+        p.x*MOON_ZOOM,p.y*MOON_ZOOM,p.z*MOON_ZOOM)+1.0)/2.0;
+    color_ramp(&MOON_RAMP, noise)
+}
+// Continent noise mixed with coastline detail at a scaled surface point;
+// land where it is below EARTH_LAND_LEVEL
+pub(crate) fn earth_land(noise: &FastNoiseLite, p: Vec3) -> f32{
+    let sample = |zoom: f32| ((noise.get_noise_3d(
+        (p.x+EARTH_NOISE_OFFSET)*zoom,(p.y+EARTH_NOISE_OFFSET)*zoom,(p.z+EARTH_NOISE_OFFSET)*zoom
+     )+1.0)/2.0).clamp(0.0, 1.0);
+    sample(EARTH_CONTINENT_ZOOM)*0.7+sample(EARTH_COAST_ZOOM)*0.3
+}
+
 fn create_map(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let zoom = 0.5;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise_big = ((uniforms.noise.get_noise_2d(
-        (x+100.0)*zoom,(y+100.0)*zoom
-     )+1.0)/2.0).max(0.0).min(1.0);
-
-     let noise_small = ((uniforms.noise.get_noise_2d(
-        (x+100.0)*8.0,(y+100.0)*8.0
-     )+1.0)/2.0).max(0.0).min(1.0);
-
-     let noise = noise_big*0.7+noise_small*0.3;
+    let noise = earth_land(&uniforms.noise, surface_point(fragment));
     let ocean = Color::from_hex(0x000d47);
 
-    let final_color = if noise<0.4{
+    if noise<EARTH_LAND_LEVEL{
         biome_color(fragment, uniforms)
     } else {
         ocean
-    };
-    final_color
+    }
 }
 
-fn biome_color(fragment: &Fragment,uniforms: &Uniforms)-> Color{ 
-    let zoom = 3.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise_small = ((uniforms.noise.get_noise_2d(
-        (x+100.0)*zoom,(y+100.0)*zoom
-     )+1.0)/2.0).max(0.0).min(1.0);
+fn biome_color(fragment: &Fragment,uniforms: &Uniforms)-> Color{
+    let zoom = EARTH_BIOME_ZOOM;
+    let p = surface_point(fragment);
+    let noise_small = ((uniforms.noise.get_noise_3d(
+        (p.x+EARTH_NOISE_OFFSET)*zoom,(p.y+EARTH_NOISE_OFFSET)*zoom,(p.z+EARTH_NOISE_OFFSET)*zoom
+     )+1.0)/2.0).clamp(0.0, 1.0);
     let greenary = Color::from_hex(0x053300);
     let desert = Color::from_hex(0x7d6902);
 
-    let final_color = if noise_small<0.2{
+    if noise_small<EARTH_DESERT_LEVEL{
         desert
-    } else if noise_small<0.8{
+    } else if noise_small<EARTH_GREENERY_LEVEL{
         let new_desert = desert*noise_small;
         greenary.blend_add(&new_desert)
     } else{
         greenary
-    };
-    final_color
+    }
 }
 
 fn cloud_shader(fragment: &Fragment, uniforms: &Uniforms)-> Color{
 
-    let x = fragment.position.x;
-    let y = fragment.position.y;
+    let p = surface_point(fragment);
     let t = uniforms.time as f32*0.5;
-    let noise_big = ((uniforms.noise.get_noise_2d(
-        (x)*1.4+t*8.0,y*1.4
-     )+1.0)/2.0).max(0.0).min(1.0);
+    let noise_big = ((uniforms.noise.get_noise_3d(
+        p.x*EARTH_CLOUD_ZOOM+t*8.0,p.y*EARTH_CLOUD_ZOOM,p.z*EARTH_CLOUD_ZOOM
+     )+1.0)/2.0).clamp(0.0, 1.0);
 
-     let noise_small = ((uniforms.noise.get_noise_2d(
-        (x)*5.0+t*15.0,y*5.0
-     )+1.0)/2.0).max(0.0).min(1.0);
+     let noise_small = ((uniforms.noise.get_noise_3d(
+        p.x*EARTH_CLOUD_DETAIL_ZOOM+t*15.0,p.y*EARTH_CLOUD_DETAIL_ZOOM,p.z*EARTH_CLOUD_DETAIL_ZOOM
+     )+1.0)/2.0).clamp(0.0, 1.0);
 
      let noise = noise_small*0.3 + noise_big*0.7;
- 
+
      let white = Color::new(255, 255, 255);
      let black  = Color::black();
 
-    let final_color = if noise<0.4{
+    if noise<EARTH_CLOUD_LEVEL{
         white*(0.6-noise)
     } else{
         black
-    };
-    final_color
+    }
 }
 // MERCURY
+// Crater noise below the floor level is a crater floor, up to the rim level its rim
+pub(crate) const MERCURY_CRATER_ZOOM: f32 = 7.0;
+pub(crate) const MERCURY_CRATER_FLOOR: f32 = 0.2;
+pub(crate) const MERCURY_CRATER_RIM: f32 = 0.25;
+pub(crate) const MERCURY_RELIEF: Displacement = Displacement { relief: Relief::Craters { zoom: MERCURY_CRATER_ZOOM }, amplitude: 0.03 };
+pub(crate) const MERCURY_RED_ZOOM: f32 = 1.5;
+pub(crate) const MERCURY_GREEN_ZOOM: f32 = 0.5;
+pub(crate) const MERCURY_BLUE_ZOOM: f32 = 0.3;
+
 pub fn mercury_shader(fragment: &Fragment, uniforms: &Uniforms)-> Color{
     let craters =mercury_craters(fragment, uniforms);
     let colors = mercury_colors(fragment, uniforms);
-    let final_color = colors.blend_multiply(&craters);
-    final_color
+    colors.blend_multiply(&craters)
 }
 
 fn mercury_craters(fragment: &Fragment, uniforms: &Uniforms)-> Color{
-    let zoom = 7.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise = (uniforms.noise.get_noise_2d(
-        x*zoom,y*zoom
+    let zoom = MERCURY_CRATER_ZOOM;
+    let p = surface_point(fragment);
+    let noise = (uniforms.noise.get_noise_3d(
+        p.x*zoom,p.y*zoom,p.z*zoom
      )+1.0)/2.0;
-    let noise = noise.max(0.0).min(1.0);
+    let noise = noise.clamp(0.0, 1.0);
 
-    let base_color = if noise<0.2{
+    if noise<MERCURY_CRATER_FLOOR{
         Color::new(255,255, 255)*(0.7-noise)
-    } else if noise<0.25{
+    } else if noise<MERCURY_CRATER_RIM{
         Color::new(255,255, 255)*(1.0-noise)
     } else {
         Color::new(255,255, 255)
-    };
-    base_color
+    }
 }
 
 fn mercury_colors(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise_r = ((uniforms.noise.get_noise_2d(
-        x*1.5,y*1.5
-     )+1.0)/2.0).max(0.0).min(1.0);
-     let noise_g = ((uniforms.noise.get_noise_2d(
-        (x+10.0)*0.5,y*0.5
-     )+1.0)/2.0).max(0.0).min(1.0);
-     let noise_b = ((uniforms.noise.get_noise_2d(
-        (x+20.0)*0.3,y*0.3
-     )+1.0)/2.0).max(0.0).min(1.0);
+    let p = surface_point(fragment);
+    let noise_r = ((uniforms.noise.get_noise_3d(
+        p.x*MERCURY_RED_ZOOM,p.y*MERCURY_RED_ZOOM,p.z*MERCURY_RED_ZOOM
+     )+1.0)/2.0).clamp(0.0, 1.0);
+     let noise_g = ((uniforms.noise.get_noise_3d(
+        (p.x+10.0)*MERCURY_GREEN_ZOOM,p.y*MERCURY_GREEN_ZOOM,p.z*MERCURY_GREEN_ZOOM
+     )+1.0)/2.0).clamp(0.0, 1.0);
+     let noise_b = ((uniforms.noise.get_noise_3d(
+        (p.x+20.0)*MERCURY_BLUE_ZOOM,p.y*MERCURY_BLUE_ZOOM,p.z*MERCURY_BLUE_ZOOM
+     )+1.0)/2.0).clamp(0.0, 1.0);
 
     let red = Color::new(255, 0, 0)*noise_r;
     let green = Color::new(0, 255, 0)*noise_g;
     let blue = Color::new(0, 0, 255)*noise_b;
     let final_color = red.blend_add(&green).blend_add(&blue);
-    final_color*(fragment.intensity.min(0.9))
+    final_color*MERCURY_LIGHTING.clamp(fragment.intensity)
 }
 
-// Venus 
+// Venus
+
+pub(crate) const VENUS_CLOUD_ZOOM: f32 = 5.0;
+pub(crate) const VENUS_CLOUD_LEVEL: f32 = 0.5;
+pub(crate) const VENUS_TEXTURE_ZOOM: f32 = 10.0;
+pub(crate) const VENUS_AREA_ZOOM: f32 = 2.0;
+pub(crate) const VENUS_TEXTURE_LEVEL: f32 = 0.4;
 
 pub fn venus_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color{
     let base_color = Color::from_hex(0xd9852b);
@@ -236,93 +410,99 @@ pub fn venus_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color{
     let texture = venus_texture(fragment, uniforms);
     let final_color = base_color.blend_subtract(&darker_color).blend_add(&light_color).blend_multiply(&texture);
 
-    final_color*(fragment.intensity.min(1.5).max(0.1))
+    final_color*VENUS_LIGHTING.clamp(fragment.intensity)
 }
 fn venus_lighter(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let zoom = 5.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise_small = (uniforms.noise.get_noise_2d(
-        x*zoom,y*zoom
+    let zoom = VENUS_CLOUD_ZOOM;
+    let p = surface_point(fragment);
+    let noise_small = (uniforms.noise.get_noise_3d(
+        p.x*zoom,p.y*zoom,p.z*zoom
      )+1.0)/2.0;
 
-     let noise_big = ((uniforms.noise.get_noise_2d(
-        x*1.0,y*1.0
-     )+1.0)/2.0);
+     let noise_big = (uniforms.noise.get_noise_3d(
+        p.x,p.y,p.z
+     )+1.0)/2.0;
 
      let noise = noise_big* 0.6+noise_small*0.4;
 
      let black = Color::black();
      let light_color = Color::from_hex(0xc28515);
-     let final_color = if noise<0.5{
+     if noise<VENUS_CLOUD_LEVEL{
         light_color*(1.0-noise)
      } else{
         black
-     };
-     final_color
+     }
 }
 
 fn venus_darker(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let zoom = 5.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise_small = (uniforms.noise.get_noise_2d(
-        x*zoom,y*zoom
+    let zoom = VENUS_CLOUD_ZOOM;
+    let p = surface_point(fragment);
+    let noise_small = (uniforms.noise.get_noise_3d(
+        p.x*zoom,p.y*zoom,p.z*zoom
      )+1.0)/2.0;
 
-     let noise_big = ((uniforms.noise.get_noise_2d(
-        (x+40.0)*1.0,y*1.0
-     )+1.0)/2.0);
+     let noise_big = (uniforms.noise.get_noise_3d(
+        p.x+40.0,p.y,p.z
+     )+1.0)/2.0;
 
      let noise = noise_big* 0.6+noise_small*0.4;
      let black = Color::black();
      let light_color = Color::from_hex(0xc28515);
-     let final_color = if noise<0.5{
+     if noise<VENUS_CLOUD_LEVEL{
         light_color*(1.0-noise)
      } else{
         black
-     };
-     final_color
+     }
 }
 
 fn venus_texture(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let zoom = 10.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise = (uniforms.noise.get_noise_2d(
-        x*zoom,y*zoom
+    let zoom = VENUS_TEXTURE_ZOOM;
+    let p = surface_point(fragment);
+    let noise = (uniforms.noise.get_noise_3d(
+        p.x*zoom,p.y*zoom,p.z*zoom
      )+1.0)/2.0;
-     let noise_area = (uniforms.noise.get_noise_2d(
-        x*2.0,y*2.0
+     let noise_area = (uniforms.noise.get_noise_3d(
+        p.x*VENUS_AREA_ZOOM,p.y*VENUS_AREA_ZOOM,p.z*VENUS_AREA_ZOOM
      )+1.0)/2.0;
      let noise = noise_area.max(noise);
      let light_color = Color::from_hex(0xffffff);
-     let final_color = if noise<0.4{
+     if noise<VENUS_TEXTURE_LEVEL{
         light_color*(1.0-noise)
      } else{
         light_color
-     };
-     final_color
+     }
 }
 
 // Jupiter
 
+// Great Red Spot, placed on the hemisphere facing the default camera.
+pub(crate) const JUPITER_SPOT_CENTER: Vec3 = Vec3::new(-0.29, -0.22, -0.34);
+pub(crate) const JUPITER_SPOT_RADIUS: f32 = 0.09;
+pub(crate) const JUPITER_SPOT_ZOOM: f32 = 20.0;
+pub(crate) const JUPITER_SPOT_RAMP: [(f32, Color); 2] = [(0.0, Color::from_hex(0x6d3701)), (1.0, Color::from_hex(0xdb6f02))];
+pub(crate) const JUPITER_STRIPE_WIDTH: f32 = 50.0;
+pub(crate) const JUPITER_STRIPE_LEVEL: f32 = 0.8;
+// Each band family stretches its noise along y by its own zoom
+pub(crate) const JUPITER_BAND_ZOOM: f32 = 3.0;
+pub(crate) const JUPITER_LIGHT_BAND_Y_ZOOM: f32 = 10.0;
+pub(crate) const JUPITER_OTHER_BAND_Y_ZOOM: f32 = 13.0;
+pub(crate) const JUPITER_DARK_LEVEL: f32 = 0.2;
+
 pub fn jupiter_shader(fragment: &Fragment, uniforms: &Uniforms)-> Color{
     let stripes = jupiter_stripes(fragment, uniforms);
     let spots = jupiter_spot(fragment, stripes, uniforms);
-    spots*(fragment.intensity.min(1.2).max(0.05))   
+    spots*JUPITER_LIGHTING.clamp(fragment.intensity)
 }
 
 fn jupiter_spot(fragment: &Fragment,color: Color, uniforms: &Uniforms)-> Color{
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let distance = ((x-500.0)*(x-500.0) + (y-350.0)*(y-350.0)).sqrt();
+    let distance = (fragment.vertex_position - JUPITER_SPOT_CENTER).magnitude();
 
-    if distance < 20.0 {
+    if distance < JUPITER_SPOT_RADIUS {
+        let p = surface_point(fragment);
         let t = uniforms.time as f32;
-        let noise = (uniforms.noise.get_noise_2d(
-            (x+t)*20.0,y*20.0)+1.0)/2.0;
-        Color::from_hex(0xdb6f02)*(noise*0.5 +0.5)
+        let noise = (uniforms.noise.get_noise_3d(
+            (p.x+t)*JUPITER_SPOT_ZOOM,p.y*JUPITER_SPOT_ZOOM,p.z*JUPITER_SPOT_ZOOM)+1.0)/2.0;
+        color_ramp(&JUPITER_SPOT_RAMP, noise)
     } else{
         color
     }
@@ -331,147 +511,140 @@ fn jupiter_stripes(fragment: &Fragment, uniforms: &Uniforms) ->Color {
     let color1 = light_stripes(fragment, uniforms);
     let color2 = other_stripes(fragment, uniforms);
 
-    let stripe_width = 50.0;
+    let stripe_width = JUPITER_STRIPE_WIDTH;
+
+    let stripe_factor = ((surface_point(fragment).y/stripe_width)*PI).sin() * 0.5 + 0.5;
 
-    let stripe_factor = ((fragment.position.y/stripe_width)*PI).sin() * 0.5 + 0.5;
-    
-   let final_color = if stripe_factor<0.8{
+    if stripe_factor<JUPITER_STRIPE_LEVEL{
         color1
     } else{
         color2
-    };
-    final_color
+    }
 }
 
 fn light_stripes(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let zoom = 3.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
+    let zoom = JUPITER_BAND_ZOOM;
+    let p = surface_point(fragment);
     let t = uniforms.time as f32 * 0.5;
-    let noise = (uniforms.noise.get_noise_2d(
-        (x+t)*zoom,y*10.0
+    let noise = (uniforms.noise.get_noise_3d(
+        (p.x+t)*zoom,p.y*JUPITER_LIGHT_BAND_Y_ZOOM,p.z*zoom
      )+1.0)/2.0;
-     let noise_area = (uniforms.noise.get_noise_2d(
-        x+t,y
+     let noise_area = (uniforms.noise.get_noise_3d(
+        p.x+t,p.y,p.z
      )+1.0)/2.0;
 
      let noise = noise.min(noise_area*2.0);
-    
+
 
      let light_color = Color::from_hex(0xffd896);
      let darker_color = Color::from_hex(0xffc86b);
-     let final_color = if noise< 0.2{
+     if noise< JUPITER_DARK_LEVEL{
         darker_color*(1.0-noise)
      } else{
         light_color*(0.5+noise).min(1.0)
-     };
-     
-     final_color
+     }
 }
 
 fn other_stripes(fragment: &Fragment, uniforms: &Uniforms)->Color{
-    let zoom = 3.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
+    let zoom = JUPITER_BAND_ZOOM;
+    let p = surface_point(fragment);
     let t = uniforms.time as f32 * 0.5;
-    let noise = (uniforms.noise.get_noise_2d(
-        (x-t)*zoom +200.0,y*13.0+200.0
+    let noise = (uniforms.noise.get_noise_3d(
+        (p.x-t)*zoom +200.0,p.y*JUPITER_OTHER_BAND_Y_ZOOM+200.0,p.z*zoom
      )+1.0)/2.0;
-     let noise_area = (uniforms.noise.get_noise_2d(
-        x-t,y
+     let noise_area = (uniforms.noise.get_noise_3d(
+        p.x-t,p.y,p.z
      )+1.0)/2.0;
 
      let noise = noise.min(noise_area*2.0);
-    
+
 
      let light_color = Color::from_hex(0xd9f6ff);
      let darker_color = Color::from_hex(0xabebff);
-     let final_color = if noise< 0.2{
+     if noise< JUPITER_DARK_LEVEL{
         darker_color*(1.0-noise)
      } else{
         light_color*(0.5+noise).min(1.0)
-     };
-     
-     final_color
+     }
 }
 
 // Saturn
 
+pub(crate) const SATURN_STRIPE_WIDTH: f32 = 5.0;
+pub(crate) const SATURN_STRIPE_LEVEL: f32 = 0.8;
+pub(crate) const SATURN_BAND_HEIGHT: f32 = 20.0;
+pub(crate) const SATURN_TEXTURE_ZOOM: f32 = 3.0;
+pub(crate) const SATURN_TEXTURE_Y_ZOOM: f32 = 10.0;
+pub(crate) const SATURN_SHADOW_LEVEL: f32 = 0.6;
+
 pub fn saturn_shader(fragment: &Fragment, uniforms: &Uniforms)-> Color{
-    let saturn_lines =saturn_lines(fragment, uniforms);
-    let ring_color = saturn_ring(fragment, uniforms, saturn_lines);
+    let saturn_lines =saturn_lines(fragment);
+    let ring_color = saturn_ring(fragment, saturn_lines);
     let final_color = saturn_texture(fragment, uniforms, ring_color);
-    final_color*(fragment.intensity.min(1.5).max(0.1))
+    final_color*SATURN_LIGHTING.clamp(fragment.intensity)
 }
 
 
-fn saturn_ring(fragment: &Fragment, uniforms: &Uniforms, color:Color) -> Color{
+// Equatorial band; it also covers the ring, which lies flat in the
+// sphere_ring.obj equator plane.
+fn saturn_ring(fragment: &Fragment, color:Color) -> Color{
     let color1 = Color::from_hex(0xff7e33);
-    let min_y = 280.0;
-    let max_y = 320.0;
+    let band_height = SATURN_BAND_HEIGHT;
 
-    
-   let final_color = if fragment.position.y < max_y && fragment.position.y > min_y{
+    if surface_point(fragment).y.abs() < band_height{
         color1
     } else {
         color
-    };
-    final_color
+    }
 }
-fn saturn_lines(fragment: &Fragment, uniforms: &Uniforms) -> Color{
+fn saturn_lines(fragment: &Fragment) -> Color{
     let color1 = Color::from_hex(0xffd885);
     let color2 = Color::from_hex(0xff9238);
 
-    let stripe_width = 5.0;
+    let stripe_width = SATURN_STRIPE_WIDTH;
 
-    let stripe_factor = ((fragment.position.y/stripe_width)*PI).sin() * 0.5 + 0.5;
-    
-   let final_color = if stripe_factor<0.8{
+    let stripe_factor = ((surface_point(fragment).y/stripe_width)*PI).sin() * 0.5 + 0.5;
+
+    if stripe_factor<SATURN_STRIPE_LEVEL{
         color1
     } else{
         color2
-    };
-    final_color
+    }
 }
 
 fn saturn_texture(fragment: &Fragment, uniforms: &Uniforms, color: Color) -> Color {
-    let zoom = 3.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise = (uniforms.noise.get_noise_2d(
-        x*zoom,y*10.0
+    let zoom = SATURN_TEXTURE_ZOOM;
+    let p = surface_point(fragment);
+    let noise = (uniforms.noise.get_noise_3d(
+        p.x*zoom,p.y*SATURN_TEXTURE_Y_ZOOM,p.z*zoom
      )+1.0)/2.0;
-    let final_color = if noise<0.6 {
+    if noise<SATURN_SHADOW_LEVEL {
         color
     } else{
         color*noise
-    };
-    final_color
+    }
 }
 
 //Neptune
 
+pub(crate) const NEPTUNE_ZOOM: f32 = 10.0;
+pub(crate) const NEPTUNE_AREA_ZOOM: f32 = 2.0;
+
 pub fn neptune_shader(fragment: &Fragment, uniforms: &Uniforms)-> Color{
     let neptune_color = neptune_texture(fragment, uniforms);
-    neptune_color*(fragment.intensity.min(2.0).max(0.05))
+    neptune_color*LightingClamp::DEFAULT.clamp(fragment.intensity)
 }
 
 fn neptune_texture(fragment: &Fragment, uniforms: &Uniforms) -> Color{
-    let zoom = 10.0;
-    let x = fragment.position.x;
-    let y = fragment.position.y;
-    let noise = (uniforms.noise.get_noise_2d(
-        x*zoom,y*zoom
+    let zoom = NEPTUNE_ZOOM;
+    let p = surface_point(fragment);
+    let noise = (uniforms.noise.get_noise_3d(
+        p.x*zoom,p.y*zoom,p.z*zoom
      )+1.0)/2.0;
-     let noise_area = (uniforms.noise.get_noise_2d(
-        x*2.0,y*2.0
-     )+1.0);
+     let noise_area = uniforms.noise.get_noise_3d(
+        p.x*NEPTUNE_AREA_ZOOM,p.y*NEPTUNE_AREA_ZOOM,p.z*NEPTUNE_AREA_ZOOM
+     )+1.0;
      let noise = noise_area.max(noise);
      let light_color = Color::from_hex(0x1350ba);
-     let final_color = if noise<0.4{
-        light_color*noise
-     } else{
-        light_color*noise
-     };
-     final_color
+     light_color*noise
 }
@@ -0,0 +1,439 @@
+use nalgebra_glm::Vec3;
+
+use crate::displacement::Displacement;
+use crate::fragments::Fragment;
+use crate::planet_definition::BlendMode;
+use crate::screen::color::Color;
+use crate::shader::{color_ramp, surface_point, FragmentShader, LightingClamp};
+use crate::uniforms::Uniforms;
+
+// A fragment shader built from nodes instead of hand-written Rust. Nodes
+// are stored in creation order and only refer to earlier nodes, so a graph
+// can never contain a cycle. Each node yields either a scalar or a color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Scalar,
+    Color,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Less,
+    Greater,
+}
+
+// Where a noise node samples, per axis:
+//     (surface + offset + time * speed) * scale + shift + time * shift_speed
+// The time terms scroll the noise over the surface.
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseSample {
+    pub scale: Vec3,
+    pub offset: Vec3,
+    pub speed: Vec3,
+    pub shift: Vec3,
+    pub shift_speed: Vec3,
+}
+
+impl NoiseSample {
+    pub fn scaled(scale: f32) -> Self {
+        NoiseSample {
+            scale: Vec3::new(scale, scale, scale),
+            offset: Vec3::zeros(),
+            speed: Vec3::zeros(),
+            shift: Vec3::zeros(),
+            shift_speed: Vec3::zeros(),
+        }
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    // Added before scaling
+    pub fn with_offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: Vec3) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    // Added after scaling
+    pub fn with_shift(mut self, shift: Vec3) -> Self {
+        self.shift = shift;
+        self
+    }
+
+    pub fn with_shift_speed(mut self, shift_speed: Vec3) -> Self {
+        self.shift_speed = shift_speed;
+        self
+    }
+
+    fn coordinates(&self, point: Vec3, time: f32) -> Vec3 {
+        let mut coordinates = Vec3::zeros();
+        for axis in 0..3 {
+            let before = self.offset[axis] + time * self.speed[axis];
+            let after = self.shift[axis] + time * self.shift_speed[axis];
+            coordinates[axis] = (point[axis] + before) * self.scale[axis] + after;
+        }
+        coordinates
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Constant(f32),
+    ColorConstant(Color),
+    // uniforms.time as a float
+    Time,
+    // The fragment's light intensity
+    Intensity,
+    // One coordinate of the scaled object-space surface point
+    Surface(Axis),
+    // Object-space distance from the fragment to a point
+    DistanceTo(Vec3),
+    // Raw noise from uniforms.noise, in about -1..1
+    Noise(NoiseSample),
+    Math(MathOp, NodeId, NodeId),
+    Sin(NodeId),
+    Abs(NodeId),
+    Clamp(NodeId, f32, f32),
+    // color * scalar
+    ScaleColor(NodeId, NodeId),
+    Blend(BlendMode, NodeId, NodeId),
+    // `then` if `value compare threshold` holds, `otherwise` if not
+    Select { value: NodeId, compare: Compare, threshold: f32, then: NodeId, otherwise: NodeId },
+    // The color at `value` along (position, color) stops sorted by position,
+    // interpolated between neighbouring stops and held past the ends
+    ColorRamp { value: NodeId, stops: Vec<(f32, Color)> },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Scalar(f32),
+    Color(Color),
+}
+
+impl Value {
+    fn scalar(self) -> f32 {
+        match self {
+            Value::Scalar(value) => value,
+            Value::Color(_) => unreachable!("graph types are checked when nodes are added"),
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Value::Color(color) => color,
+            Value::Scalar(_) => unreachable!("graph types are checked when nodes are added"),
+        }
+    }
+}
+
+// Adds nodes one at a time; every method returns the id of the new node.
+// Wiring a color where a scalar is expected (or the other way) panics.
+#[derive(Debug, Clone, Default)]
+pub struct GraphBuilder {
+    nodes: Vec<Node>,
+    types: Vec<ValueType>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        GraphBuilder::default()
+    }
+
+    fn expect(&self, id: NodeId, expected: ValueType) {
+        assert_eq!(self.types[id.0], expected, "node {} is a {:?}, expected a {:?}", id.0, self.types[id.0], expected);
+    }
+
+    fn push(&mut self, node: Node, value_type: ValueType) -> NodeId {
+        self.nodes.push(node);
+        self.types.push(value_type);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn constant(&mut self, value: f32) -> NodeId {
+        self.push(Node::Constant(value), ValueType::Scalar)
+    }
+
+    pub fn color(&mut self, color: Color) -> NodeId {
+        self.push(Node::ColorConstant(color), ValueType::Color)
+    }
+
+    pub fn time(&mut self) -> NodeId {
+        self.push(Node::Time, ValueType::Scalar)
+    }
+
+    pub fn intensity(&mut self) -> NodeId {
+        self.push(Node::Intensity, ValueType::Scalar)
+    }
+
+    pub fn surface(&mut self, axis: Axis) -> NodeId {
+        self.push(Node::Surface(axis), ValueType::Scalar)
+    }
+
+    pub fn distance_to(&mut self, point: Vec3) -> NodeId {
+        self.push(Node::DistanceTo(point), ValueType::Scalar)
+    }
+
+    pub fn noise(&mut self, sample: NoiseSample) -> NodeId {
+        self.push(Node::Noise(sample), ValueType::Scalar)
+    }
+
+    // Noise remapped to 0..1 as (noise + 1) / 2
+    pub fn noise01(&mut self, sample: NoiseSample) -> NodeId {
+        let noise = self.noise(sample);
+        let one = self.constant(1.0);
+        let two = self.constant(2.0);
+        let shifted = self.add(noise, one);
+        self.div(shifted, two)
+    }
+
+    pub fn math(&mut self, op: MathOp, a: NodeId, b: NodeId) -> NodeId {
+        self.expect(a, ValueType::Scalar);
+        self.expect(b, ValueType::Scalar);
+        self.push(Node::Math(op, a, b), ValueType::Scalar)
+    }
+
+    pub fn add(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.math(MathOp::Add, a, b)
+    }
+
+    pub fn sub(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.math(MathOp::Sub, a, b)
+    }
+
+    pub fn mul(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.math(MathOp::Mul, a, b)
+    }
+
+    pub fn div(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.math(MathOp::Div, a, b)
+    }
+
+    pub fn min(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.math(MathOp::Min, a, b)
+    }
+
+    pub fn max(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        self.math(MathOp::Max, a, b)
+    }
+
+    pub fn sin(&mut self, value: NodeId) -> NodeId {
+        self.expect(value, ValueType::Scalar);
+        self.push(Node::Sin(value), ValueType::Scalar)
+    }
+
+    pub fn abs(&mut self, value: NodeId) -> NodeId {
+        self.expect(value, ValueType::Scalar);
+        self.push(Node::Abs(value), ValueType::Scalar)
+    }
+
+    pub fn clamp(&mut self, value: NodeId, min: f32, max: f32) -> NodeId {
+        self.expect(value, ValueType::Scalar);
+        self.push(Node::Clamp(value, min, max), ValueType::Scalar)
+    }
+
+    pub fn scale_color(&mut self, color: NodeId, factor: NodeId) -> NodeId {
+        self.expect(color, ValueType::Color);
+        self.expect(factor, ValueType::Scalar);
+        self.push(Node::ScaleColor(color, factor), ValueType::Color)
+    }
+
+    pub fn blend(&mut self, mode: BlendMode, base: NodeId, layer: NodeId) -> NodeId {
+        self.expect(base, ValueType::Color);
+        self.expect(layer, ValueType::Color);
+        self.push(Node::Blend(mode, base, layer), ValueType::Color)
+    }
+
+    // Both branches must have the same type
+    pub fn select(&mut self, value: NodeId, compare: Compare, threshold: f32, then: NodeId, otherwise: NodeId) -> NodeId {
+        self.expect(value, ValueType::Scalar);
+        let branch_type = self.types[then.0];
+        self.expect(otherwise, branch_type);
+        self.push(Node::Select { value, compare, threshold, then, otherwise }, branch_type)
+    }
+
+    // Stops may come in any order; there must be at least one
+    pub fn color_ramp(&mut self, value: NodeId, mut stops: Vec<(f32, Color)>) -> NodeId {
+        self.expect(value, ValueType::Scalar);
+        assert!(!stops.is_empty(), "a color ramp needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.push(Node::ColorRamp { value, stops }, ValueType::Color)
+    }
+
+    // The output must be a color
+    pub fn build(self, output: NodeId) -> ShaderGraph {
+        self.expect(output, ValueType::Color);
+        ShaderGraph { nodes: self.nodes, output, lighting: LightingClamp::DEFAULT, displacement: Displacement::default() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderGraph {
+    nodes: Vec<Node>,
+    output: NodeId,
+    lighting: LightingClamp,
+    displacement: Displacement,
+}
+
+impl ShaderGraph {
+    // The clamp the graph applies to the intensity node, reported to the
+    // bake tool; building a graph doesn't work it out
+    pub fn with_lighting(mut self, lighting: LightingClamp) -> Self {
+        self.lighting = lighting;
+        self
+    }
+
+    // The relief the exporter gives the graph's planet
+    pub fn with_displacement(mut self, displacement: Displacement) -> Self {
+        self.displacement = displacement;
+        self
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    // Nodes are evaluated on demand, so a select only runs the branch it
+    // takes, and each node at most once per fragment
+    fn evaluate(&self, id: NodeId, fragment: &Fragment, uniforms: &Uniforms, cache: &mut [Option<Value>]) -> Value {
+        if let Some(value) = cache[id.0] {
+            return value;
+        }
+        let scalar = |id: NodeId, cache: &mut [Option<Value>]| self.evaluate(id, fragment, uniforms, cache).scalar();
+
+        let value = match &self.nodes[id.0] {
+            Node::Constant(value) => Value::Scalar(*value),
+            Node::ColorConstant(color) => Value::Color(*color),
+            Node::Time => Value::Scalar(uniforms.time as f32),
+            Node::Intensity => Value::Scalar(fragment.intensity),
+            Node::Surface(axis) => {
+                let p = surface_point(fragment);
+                Value::Scalar(match axis {
+                    Axis::X => p.x,
+                    Axis::Y => p.y,
+                    Axis::Z => p.z,
+                })
+            }
+            Node::DistanceTo(point) => Value::Scalar((fragment.vertex_position - point).magnitude()),
+            Node::Noise(sample) => {
+                let c = sample.coordinates(surface_point(fragment), uniforms.time as f32);
+                Value::Scalar(uniforms.noise.get_noise_3d(c.x, c.y, c.z))
+            }
+            Node::Math(op, a, b) => {
+                let (a, b) = (scalar(*a, cache), scalar(*b, cache));
+                Value::Scalar(match op {
+                    MathOp::Add => a + b,
+                    MathOp::Sub => a - b,
+                    MathOp::Mul => a * b,
+                    MathOp::Div => a / b,
+                    MathOp::Min => a.min(b),
+                    MathOp::Max => a.max(b),
+                })
+            }
+            Node::Sin(value) => Value::Scalar(scalar(*value, cache).sin()),
+            Node::Abs(value) => Value::Scalar(scalar(*value, cache).abs()),
+            Node::Clamp(value, min, max) => Value::Scalar(scalar(*value, cache).clamp(*min, *max)),
+            Node::ScaleColor(color, factor) => {
+                let color = self.evaluate(*color, fragment, uniforms, cache).color();
+                Value::Color(color * scalar(*factor, cache))
+            }
+            Node::Blend(mode, base, layer) => {
+                let base = self.evaluate(*base, fragment, uniforms, cache).color();
+                let layer = self.evaluate(*layer, fragment, uniforms, cache).color();
+                Value::Color(mode.blend(&base, &layer))
+            }
+            Node::Select { value, compare, threshold, then, otherwise } => {
+                let value = scalar(*value, cache);
+                let holds = match compare {
+                    Compare::Less => value < *threshold,
+                    Compare::Greater => value > *threshold,
+                };
+                self.evaluate(if holds { *then } else { *otherwise }, fragment, uniforms, cache)
+            }
+            Node::ColorRamp { value, stops } => Value::Color(color_ramp(stops, scalar(*value, cache))),
+        };
+        cache[id.0] = Some(value);
+        value
+    }
+}
+
+impl FragmentShader for ShaderGraph {
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+        let mut cache = vec![None; self.nodes.len()];
+        self.evaluate(self.output, fragment, uniforms, &mut cache).color()
+    }
+
+    fn lighting(&self) -> LightingClamp {
+        self.lighting
+    }
+
+    fn displacement(&self) -> Displacement {
+        self.displacement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "expected a Scalar")]
+    fn rejects_colors_in_math() {
+        let mut graph = GraphBuilder::new();
+        let red = graph.color(Color::new(255, 0, 0));
+        let one = graph.constant(1.0);
+        graph.add(red, one);
+    }
+
+    #[test]
+    fn noise_coordinates_apply_offsets_in_order() {
+        let sample = NoiseSample::scaled(2.0)
+            .with_offset(Vec3::new(1.0, 0.0, 0.0))
+            .with_speed(Vec3::new(0.5, 0.0, 0.0))
+            .with_shift(Vec3::new(0.0, 3.0, 0.0))
+            .with_shift_speed(Vec3::new(0.0, 0.0, 1.0));
+        let c = sample.coordinates(Vec3::new(1.0, 1.0, 1.0), 4.0);
+        assert_eq!(c, Vec3::new((1.0 + 1.0 + 2.0) * 2.0, 2.0 + 3.0, 2.0 + 4.0));
+    }
+
+    #[test]
+    fn color_ramp_interpolates_between_sorted_stops() {
+        let mut graph = GraphBuilder::new();
+        let value = graph.constant(0.0);
+        let stops = vec![(1.0, Color::new(200, 0, 100)), (0.0, Color::new(0, 0, 0)), (0.5, Color::new(100, 100, 100))];
+        graph.color_ramp(value, stops);
+        let Node::ColorRamp { stops, .. } = &graph.nodes[1] else { panic!("expected a color ramp") };
+        let sample = |value: f32| color_ramp(stops, value).to_hex();
+
+        assert_eq!(sample(-1.0), 0x000000);
+        assert_eq!(sample(0.25), 0x323232);
+        assert_eq!(sample(0.75), 0x963264);
+        assert_eq!(sample(2.0), 0xc80064);
+    }
+}
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use nalgebra_glm::Vec3;
+
+use crate::lod::LodMesh;
+use crate::obj::{PlanetMeshes, RING_INNER_RADIUS, RING_OUTER_RADIUS, SPHERE_RADIUS};
+use crate::orbit::{AxialRotation, OrbitalElements};
+use crate::registry::ShaderRegistry;
+use crate::scene::{SceneLight, SceneNode, Transform};
+use crate::uniforms::CullMode;
+
+// Every body gets an orbit node placing it around its parent and a body
+// node below it holding the mesh. Moons hang off their planet's orbit node.
+struct Body {
+    name: &'static str,
+    orbit: OrbitalElements,
+    spin: AxialRotation,
+    scale: f32,
+}
+
+// Real eccentricities, angles, periods and sidereal days (in days); only
+// the distances and sizes are compressed to fit the scene.
+const PLANETS: [Body; 6] = [
+    Body { name: "mercury", orbit: elements(3.5, 0.2056, 7.0, 48.33, 29.12, 252.25, 87.97), spin: spin(58.646, 0.03), scale: 0.4 },
+    Body { name: "venus", orbit: elements(5.0, 0.0068, 3.39, 76.68, 54.88, 181.98, 224.70), spin: spin(243.025, 177.36), scale: 0.7 },
+    Body { name: "earth", orbit: elements(7.0, 0.0167, 0.0, 0.0, 102.94, 100.46, 365.26), spin: spin(0.99727, 23.44), scale: 0.75 },
+    Body { name: "jupiter", orbit: elements(10.5, 0.0484, 1.30, 100.46, 273.87, 34.40, 4332.59), spin: spin(0.41354, 3.13), scale: 2.0 },
+    Body { name: "saturn", orbit: elements(14.5, 0.0539, 2.49, 113.67, 339.39, 49.94, 10759.22), spin: spin(0.44401, 26.73), scale: 1.6 },
+    Body { name: "neptune", orbit: elements(18.0, 0.0086, 1.77, 131.78, 276.34, 304.88, 60190.03), spin: spin(0.67125, 28.32), scale: 1.1 },
+];
+
+// Tidally locked, so it turns once per orbit
+const MOON: Body = Body { name: "moon", orbit: elements(0.9, 0.0549, 5.14, 125.08, 318.15, 218.32, 27.32), spin: spin(27.32, 6.68), scale: 0.2 };
+const BODY_NAMES: [&str; 8] = ["sun", "mercury", "venus", "earth", "moon", "jupiter", "saturn", "neptune"];
+const SUN_SPIN: AxialRotation = spin(25.38, 7.25);
+const SUN_SCALE: f32 = 4.0;
+
+// Angles in degrees; the mean longitude at epoch is node + periapsis + mean anomaly
+const fn elements(semi_major_axis: f32, eccentricity: f32, inclination: f32, ascending_node: f32, argument_of_periapsis: f32, mean_longitude: f32, period: f32) -> OrbitalElements {
+    OrbitalElements {
+        semi_major_axis,
+        eccentricity,
+        inclination: inclination.to_radians(),
+        ascending_node: ascending_node.to_radians(),
+        argument_of_periapsis: argument_of_periapsis.to_radians(),
+        mean_anomaly_at_epoch: (mean_longitude - ascending_node - argument_of_periapsis).to_radians(),
+        period,
+    }
+}
+
+// Tilt in degrees
+const fn spin(period: f32, axial_tilt: f32) -> AxialRotation {
+    AxialRotation { period, axial_tilt: axial_tilt.to_radians() }
+}
+
+// Name of the node that carries a body's mesh
+pub fn body_node_name(name: &str) -> String {
+    format!("{} body", name)
+}
+
+// The sun lights everything from the origin
+pub const SUN_LIGHT: SceneLight = SceneLight::Point(Vec3::new(0.0, 0.0, 0.0));
+
+// Bodies switch to coarser spheres as they shrink on screen; call
+// lod::update_lods before each render
+pub fn build_solar_system(registry: &ShaderRegistry, meshes: &PlanetMeshes) -> Result<SceneNode, String> {
+    let program = |name: &str| {
+        registry.get(name).cloned().ok_or_else(|| format!("unknown planet '{}'", name))
+    };
+    let sphere = Arc::new(LodMesh::planet(meshes.sphere.clone(), SPHERE_RADIUS, None));
+    let ring = Arc::new(LodMesh::planet(meshes.ring.clone(), SPHERE_RADIUS, Some((RING_INNER_RADIUS, RING_OUTER_RADIUS))));
+
+    let mut sun = SceneNode::new("sun");
+    sun = sun.with_child(
+        SceneNode::new(&body_node_name("sun"))
+            .with_transform(scaled(SUN_SCALE))
+            .with_lods(sphere.clone(), program("sun")?),
+    );
+
+    for planet in &PLANETS {
+        // The ring mesh is single-sided, so its back must not be culled
+        let body = if planet.name == "saturn" {
+            SceneNode::new(&body_node_name(planet.name))
+                .with_lods(ring.clone(), program(planet.name)?)
+                .with_cull_mode(CullMode::None)
+        } else {
+            SceneNode::new(&body_node_name(planet.name))
+                .with_lods(sphere.clone(), program(planet.name)?)
+        };
+
+        let mut orbit = SceneNode::new(planet.name)
+            .with_child(body.with_transform(scaled(planet.scale)));
+
+        if planet.name == "earth" {
+            orbit = orbit.with_child(
+                SceneNode::new(MOON.name)
+                    .with_child(
+                        SceneNode::new(&body_node_name(MOON.name))
+                            .with_transform(scaled(MOON.scale))
+                            .with_lods(sphere.clone(), program(MOON.name)?),
+                    ),
+            );
+        }
+        sun = sun.with_child(orbit);
+    }
+    update_bodies(&mut sun, 0.0);
+    Ok(sun)
+}
+
+// Moves every orbit node to where its body is after `days` of simulated
+// time and turns every body node about its tilted axis
+pub fn update_bodies(scene: &mut SceneNode, days: f64) {
+    for body in PLANETS.iter().chain([&MOON]) {
+        if let Some(node) = scene.find_mut(body.name) {
+            node.transform.translation = body.orbit.position(days);
+        }
+    }
+    for name in BODY_NAMES {
+        if let (Some(node), Some(rotation)) = (scene.find_mut(&body_node_name(name)), body_rotation(name, days)) {
+            node.transform.rotation = rotation;
+        }
+    }
+}
+
+// Gives every body the registry's current program of the same name, e.g.
+// after planet files were reloaded
+pub fn update_programs(scene: &mut SceneNode, registry: &ShaderRegistry) {
+    for name in BODY_NAMES {
+        if let (Some(node), Some(program)) = (scene.find_mut(&body_node_name(name)), registry.get(name)) {
+            node.program = Some(program.clone());
+        }
+    }
+}
+
+// Rotation of a body after `days`, for create_model_matrix
+pub fn body_rotation(name: &str, days: f64) -> Option<Vec3> {
+    if name == "sun" {
+        return Some(SUN_SPIN.rotation(days));
+    }
+    PLANETS.iter().chain([&MOON])
+        .find(|body| body.name == name)
+        .map(|body| body.spin.rotation(days))
+}
+
+fn scaled(scale: f32) -> Transform {
+    Transform { scale, ..Transform::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planet_definition::PlanetDefinition;
+
+    #[test]
+    fn every_body_is_in_the_scene() {
+        let meshes = PlanetMeshes::load("./assets/3d_models").unwrap();
+        let scene = build_solar_system(&ShaderRegistry::with_planets(), &meshes).unwrap();
+        for name in BODY_NAMES {
+            let body = scene.find(&body_node_name(name)).expect(name);
+            assert!(body.mesh.is_some() && body.program.is_some());
+        }
+    }
+
+    #[test]
+    fn bodies_pick_up_replaced_programs() {
+        let meshes = PlanetMeshes::generate();
+        let mut registry = ShaderRegistry::with_planets();
+        let mut scene = build_solar_system(&registry, &meshes).unwrap();
+        let definition = PlanetDefinition::parse("name = \"venus\"\n[[layers]]\nramp = [{ at = 0.0, color = \"#ffffff\" }]").unwrap();
+        registry.register(definition.into_program());
+
+        update_programs(&mut scene, &registry);
+        for name in BODY_NAMES {
+            let body = scene.find(&body_node_name(name)).unwrap().program.as_ref().unwrap();
+            assert!(Arc::ptr_eq(&body.fragment, &registry.get(name).unwrap().fragment), "{}", name);
+        }
+    }
+
+    #[test]
+    fn moon_follows_the_earth() {
+        let meshes = PlanetMeshes::load("./assets/3d_models").unwrap();
+        let mut scene = build_solar_system(&ShaderRegistry::with_planets(), &meshes).unwrap();
+        for days in [0.0, 100.0, 1000.0] {
+            update_bodies(&mut scene, days);
+            let earth = scene.world_position("earth").unwrap();
+            let moon = scene.world_position("moon").unwrap();
+            assert!((earth - PLANETS[2].orbit.position(days)).magnitude() < 1e-4);
+            assert!((moon - earth - MOON.orbit.position(days)).magnitude() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn bodies_spin_about_their_tilted_axis() {
+        let meshes = PlanetMeshes::load("./assets/3d_models").unwrap();
+        let mut scene = build_solar_system(&ShaderRegistry::with_planets(), &meshes).unwrap();
+        update_bodies(&mut scene, 0.25);
+        let earth = scene.find(&body_node_name("earth")).unwrap().transform;
+        assert!((earth.rotation.y - std::f32::consts::TAU * (0.25 / 0.99727)).abs() < 1e-4);
+        assert!((earth.rotation.z - 23.44f32.to_radians()).abs() < 1e-6);
+        // Spinning keeps the body's scale
+        assert_eq!(earth.scale, 0.75);
+    }
+
+    #[test]
+    fn coarse_rings_match_the_model() {
+        let meshes = PlanetMeshes::load("./assets/3d_models").unwrap();
+        let ring = LodMesh::planet(meshes.ring.clone(), SPHERE_RADIUS, Some((RING_INNER_RADIUS, RING_OUTER_RADIUS)));
+        let ring_extent = |level: usize| {
+            ring.level(level).mesh.vertices.iter()
+                .map(|vertex| vertex.position.xz().magnitude())
+                .filter(|&radius| radius > 0.75)
+                .fold((f32::MAX, 0.0f32), |(inner, outer), radius| (inner.min(radius), outer.max(radius)))
+        };
+        let (inner, outer) = ring_extent(0);
+        for level in 1..ring.len() {
+            let (coarse_inner, coarse_outer) = ring_extent(level);
+            assert!((coarse_inner - inner).abs() < 0.01 && (coarse_outer - outer).abs() < 0.01, "level {}", level);
+        }
+    }
+}
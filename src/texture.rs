@@ -0,0 +1,318 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use nalgebra_glm::Vec2;
+use crate::fragments::Fragment;
+use crate::screen::color::Color;
+use crate::screen::framebuffer::Framebuffer;
+use crate::screen::image::load_image;
+use crate::shader::{FragmentShader, LightingClamp};
+use crate::uniforms::Uniforms;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+// What happens to coordinates outside 0..1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+}
+
+// With mipmaps on, samples blend the two mip levels closest to the
+// fragment's footprint; otherwise only the full-size image is read
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+    pub mipmaps: bool,
+}
+
+impl Sampler {
+    // For maps wrapped around a sphere: longitude repeats, the poles don't
+    pub fn equirectangular() -> Self {
+        Sampler::default().with_wrap_v(Wrap::Clamp)
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // Same mode along u and v
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap_u = wrap;
+        self.wrap_v = wrap;
+        self
+    }
+
+    pub fn with_wrap_v(mut self, wrap: Wrap) -> Self {
+        self.wrap_v = wrap;
+        self
+    }
+
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler { filter: Filter::Bilinear, wrap_u: Wrap::Repeat, wrap_v: Wrap::Repeat, mipmaps: true }
+    }
+}
+
+fn wrap_coordinate(coordinate: i32, size: usize, wrap: Wrap) -> usize {
+    match wrap {
+        Wrap::Repeat => coordinate.rem_euclid(size as i32) as usize,
+        Wrap::Clamp => coordinate.clamp(0, size as i32 - 1) as usize,
+    }
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: i32, y: i32, sampler: &Sampler) -> Color {
+        let x = wrap_coordinate(x, self.width, sampler.wrap_u);
+        let y = wrap_coordinate(y, self.height, sampler.wrap_v);
+        self.texels[y * self.width + x]
+    }
+
+    // Box filter over 2x2 blocks. With an odd width or height the last
+    // column or row of blocks is three texels wide, so no texel is skipped.
+    fn half(&self) -> MipLevel {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        // Source texels covered by output texel `i` of `count`
+        let span = |i: usize, count: usize, size: usize| {
+            if i + 1 == count { i * 2..size } else { i * 2..i * 2 + 2 }
+        };
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (columns, rows) = (span(x, width, self.width), span(y, height, self.height));
+                let block: Vec<Color> = rows
+                    .flat_map(|y| columns.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| self.texels[y * self.width + x])
+                    .collect();
+                let count = block.len() as u32;
+                let average = |channel: fn(&Color) -> u8| {
+                    (block.iter().map(|color| channel(color) as u32).sum::<u32>() + count / 2) / count
+                };
+                texels.push(Color::new(
+                    average(|color| color.r) as i32,
+                    average(|color| color.g) as i32,
+                    average(|color| color.b) as i32,
+                ));
+            }
+        }
+        MipLevel { width, height, texels }
+    }
+}
+
+// An RGB image with its full mip chain, sampled with OBJ-style texture
+// coordinates: u runs left to right and v bottom to top
+pub struct Texture {
+    levels: Vec<MipLevel>,
+}
+
+impl Texture {
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0, "texture must not be empty");
+        assert_eq!(texels.len(), width * height, "texel count does not match the texture size");
+
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(last) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            let next = last.half();
+            levels.push(next);
+        }
+        Texture { levels }
+    }
+
+    pub fn from_framebuffer(framebuffer: &Framebuffer) -> Self {
+        Texture::new(framebuffer.width, framebuffer.height, framebuffer.buffer.clone())
+    }
+
+    // Reads a .png or .ppm image
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let image = load_image(path)?;
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: empty image", path.display())));
+        }
+        Ok(Texture::from_framebuffer(&image))
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level_size(&self, level: usize) -> (usize, usize) {
+        (self.levels[level].width, self.levels[level].height)
+    }
+
+    pub fn texel(&self, level: usize, x: usize, y: usize) -> Color {
+        self.levels[level].texel(x as i32, y as i32, &Sampler::default().with_wrap(Wrap::Clamp))
+    }
+
+    // Samples the full-size image
+    pub fn sample(&self, uv: Vec2, sampler: &Sampler) -> Color {
+        self.sample_level(0, uv, sampler)
+    }
+
+    // Picks the mip level from how far the texture coordinates move per
+    // pixel along screen x (`dx`) and y (`dy`)
+    pub fn sample_grad(&self, uv: Vec2, dx: Vec2, dy: Vec2, sampler: &Sampler) -> Color {
+        self.sample_lod(uv, self.lod(dx, dy), sampler)
+    }
+
+    // Level of detail 0 is the full image, each step up halves its size
+    pub fn sample_lod(&self, uv: Vec2, lod: f32, sampler: &Sampler) -> Color {
+        let max_lod = (self.levels.len() - 1) as f32;
+        if !sampler.mipmaps || lod <= 0.0 || max_lod == 0.0 {
+            return self.sample_level(0, uv, sampler);
+        }
+        let lod = lod.min(max_lod);
+        let base = lod.floor();
+        let blend = lod - base;
+        let near = self.sample_level(base as usize, uv, sampler);
+        if blend == 0.0 {
+            return near;
+        }
+        near.lerp(&self.sample_level(base as usize + 1, uv, sampler), blend)
+    }
+
+    pub fn lod(&self, dx: Vec2, dy: Vec2) -> f32 {
+        let size = Vec2::new(self.width() as f32, self.height() as f32);
+        let footprint = dx.component_mul(&size).magnitude().max(dy.component_mul(&size).magnitude());
+        if footprint > 1.0 { footprint.log2() } else { 0.0 }
+    }
+
+    fn sample_level(&self, level: usize, uv: Vec2, sampler: &Sampler) -> Color {
+        let level = &self.levels[level];
+        let x = uv.x * level.width as f32;
+        let y = (1.0 - uv.y) * level.height as f32;
+        match sampler.filter {
+            Filter::Nearest => level.texel(x.floor() as i32, y.floor() as i32, sampler),
+            Filter::Bilinear => {
+                // Texel centers sit at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+                let top = level.texel(x0, y0, sampler).lerp(&level.texel(x0 + 1, y0, sampler), fx);
+                let bottom = level.texel(x0, y0 + 1, sampler).lerp(&level.texel(x0 + 1, y0 + 1, sampler), fx);
+                top.lerp(&bottom, fy)
+            }
+        }
+    }
+}
+
+// Colors a mesh from a texture using its interpolated texture coordinates,
+// lit the same way as the procedural planets
+#[derive(Clone)]
+pub struct TextureShader {
+    pub texture: Arc<Texture>,
+    pub sampler: Sampler,
+}
+
+impl TextureShader {
+    pub fn new(texture: Arc<Texture>) -> Self {
+        TextureShader { texture, sampler: Sampler::equirectangular() }
+    }
+
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+}
+
+impl FragmentShader for TextureShader {
+    fn shade(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+        let color = self.texture.sample_grad(fragment.tex_coords, fragment.tex_coords_dx, fragment.tex_coords_dy, &self.sampler);
+        color * LightingClamp::DEFAULT.clamp(fragment.intensity)
+    }
+
+    fn needs_derivatives(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2: red, green on the top row, blue, white on the bottom row
+    fn quad_texture() -> Texture {
+        let texels = [0xff0000, 0x00ff00, 0x0000ff, 0xffffff].map(Color::from_hex).to_vec();
+        Texture::new(2, 2, texels)
+    }
+
+    #[test]
+    fn nearest_sampling_wraps_or_clamps() {
+        let texture = quad_texture();
+        let nearest = Sampler::default().with_filter(Filter::Nearest);
+        // v runs bottom to top
+        assert_eq!(texture.sample(Vec2::new(0.25, 0.75), &nearest).to_hex(), 0xff0000);
+        assert_eq!(texture.sample(Vec2::new(0.25, 0.25), &nearest).to_hex(), 0x0000ff);
+        assert_eq!(texture.sample(Vec2::new(1.25, 0.75), &nearest).to_hex(), 0xff0000);
+        let clamped = nearest.with_wrap(Wrap::Clamp);
+        assert_eq!(texture.sample(Vec2::new(1.25, 0.75), &clamped).to_hex(), 0x00ff00);
+    }
+
+    #[test]
+    fn bilinear_blends_neighbouring_texels() {
+        let texture = quad_texture();
+        let bilinear = Sampler::default().with_wrap(Wrap::Clamp);
+        // Exactly on a texel center
+        assert_eq!(texture.sample(Vec2::new(0.75, 0.25), &bilinear).to_hex(), 0xffffff);
+        // Halfway between red and green
+        assert_eq!(texture.sample(Vec2::new(0.5, 0.75), &bilinear).to_hex(), 0x808000);
+    }
+
+    #[test]
+    fn mip_chain_averages_down_to_one_texel() {
+        let texture = quad_texture();
+        assert_eq!(texture.level_count(), 2);
+        assert_eq!(texture.level_size(1), (1, 1));
+        assert_eq!(texture.texel(1, 0, 0).to_hex(), 0x808080);
+
+        let odd = Texture::new(5, 3, vec![Color::from_hex(0x204060); 15]);
+        let sizes: Vec<_> = (0..odd.level_count()).map(|level| odd.level_size(level)).collect();
+        assert_eq!(sizes, vec![(5, 3), (2, 1), (1, 1)]);
+
+        // 5x5 with a white last column and row: the 3-wide edge blocks keep them
+        let edges = (0..25)
+            .map(|i| if i % 5 == 4 || i / 5 == 4 { Color::new(255, 255, 255) } else { Color::black() })
+            .collect();
+        let edges = Texture::new(5, 5, edges);
+        assert_eq!(edges.level_size(1), (2, 2));
+        let gray = |x, y| edges.texel(1, x, y).r;
+        assert_eq!([gray(0, 0), gray(1, 0), gray(0, 1), gray(1, 1)], [0, 85, 85, 142]);
+
+        // A footprint of 2 texels per pixel reads level 1 only
+        let uv = Vec2::new(0.5, 0.5);
+        let step = Vec2::new(1.0, 0.0);
+        assert_eq!(texture.lod(step, Vec2::zeros()), 1.0);
+        assert_eq!(texture.sample_grad(uv, step, Vec2::zeros(), &Sampler::default()).to_hex(), 0x808080);
+        let corner = Vec2::new(0.75, 0.25);
+        let flat = Sampler::default().with_mipmaps(false);
+        assert_eq!(texture.sample_grad(corner, step, Vec2::zeros(), &flat).to_hex(), 0xffffff);
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::bounding_box::calculate_bounding_box;
+use crate::fragments::triangle_fill;
+use crate::screen::framebuffer::Framebuffer;
+use crate::shader::FragmentShader;
+use crate::uniforms::Uniforms;
+use crate::vertex::Vertex;
+
+pub const TILE_SIZE: usize = 32;
+
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    // Positions in the frame's triangle list, in submission order
+    pub triangles: Vec<usize>,
+}
+
+// Splits the screen into tiles and records which triangles' bounding boxes
+// touch each one. Triangles are indices into `vertices`.
+pub fn bin_triangles(width: usize, height: usize, vertices: &[Vertex], triangles: &[[u32; 3]]) -> Vec<Tile> {
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tiles_y = height.div_ceil(TILE_SIZE);
+
+    let mut tiles = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let x = tx * TILE_SIZE;
+            let y = ty * TILE_SIZE;
+            tiles.push(Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - y),
+                triangles: Vec::new(),
+            });
+        }
+    }
+
+    for (index, tri) in triangles.iter().enumerate() {
+        let [v1, v2, v3] = tri.map(|index| &vertices[index as usize].transformed_position);
+        let (min_x, min_y, max_x, max_y) = calculate_bounding_box(v1, v2, v3);
+        let min_x = min_x.clamp(0, width as i32) as usize;
+        let min_y = min_y.clamp(0, height as i32) as usize;
+        let max_x = max_x.clamp(0, width as i32) as usize;
+        let max_y = max_y.clamp(0, height as i32) as usize;
+        if min_x >= max_x || min_y >= max_y {
+            continue;
+        }
+
+        for ty in min_y / TILE_SIZE..=(max_y - 1) / TILE_SIZE {
+            for tx in min_x / TILE_SIZE..=(max_x - 1) / TILE_SIZE {
+                tiles[ty * tiles_x + tx].triangles.push(index);
+            }
+        }
+    }
+    tiles
+}
+
+// Rasterizes and shades every tile on a pool of `threads` workers. Each tile
+// walks its triangles in submission order against its own copy of the
+// framebuffer region (depth included, for the early depth test), so the
+// result matches the serial rasterizer exactly.
+pub fn rasterize_tiles(framebuffer: &mut Framebuffer, vertices: &[Vertex], triangles: &[[u32; 3]], uniforms: &Uniforms, fragment_shader: &dyn FragmentShader, threads: usize) {
+    let tiles = bin_triangles(framebuffer.width, framebuffer.height, vertices, triangles);
+    let next_tile = AtomicUsize::new(0);
+    let source = &*framebuffer;
+    let derivatives = fragment_shader.needs_derivatives();
+
+    let finished: Vec<(usize, Framebuffer)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| {
+            scope.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(index) else { break };
+                    if tile.triangles.is_empty() {
+                        continue;
+                    }
+
+                    let mut target = source.read_region(tile.x, tile.y, tile.width, tile.height);
+                    for &triangle in &tile.triangles {
+                        let corners = triangles[triangle].map(|index| &vertices[index as usize]);
+                        triangle_fill(corners, uniforms, &mut target, (tile.x, tile.y), derivatives, |fragment| {
+                            fragment_shader.shade(fragment, uniforms)
+                        });
+                    }
+                    done.push((index, target));
+                }
+                done
+            })
+        }).collect();
+
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("rasterizer thread panicked"))
+            .collect()
+    });
+
+    for (index, target) in finished {
+        framebuffer.write_region(tiles[index].x, tiles[index].y, &target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{look_at, perspective, Mat4, Vec3};
+    use crate::obj::Obj;
+    use crate::registry::{ShaderProgram, ShaderRegistry};
+    use crate::uniforms::{render_with_threads, CullMode, Winding};
+
+    fn planet_uniforms(program: &ShaderProgram, width: f32, height: f32) -> Uniforms {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: look_at(&Vec3::new(0.3, 0.8, -1.6), &Vec3::new(0.0, 0.0, 0.0), &Vec3::new(0.0, 1.0, 0.0)),
+            projection_matrix: perspective(width / height, 45.0f32.to_radians(), 0.1, 1000.0),
+            viewport_matrix: Mat4::new(
+                width / 2.0, 0.0, 0.0, width / 2.0,
+                0.0, -height / 2.0, 0.0, height / 2.0,
+                0.0, 0.0, 1.0, 0.0,
+                0.0, 0.0, 0.0, 1.0
+            ),
+            light_dir: Vec3::new(1.0, 3.0, -4.0),
+            time: 7,
+            noise: program.create_noise(),
+            cull_mode: CullMode::Back,
+            front_face: Winding::CounterClockwise,
+        }
+    }
+
+    #[test]
+    fn tiled_output_matches_serial() {
+        // Odd sizes leave partial tiles on the right and bottom edges
+        let (width, height) = (133, 101);
+        let sphere = Obj::load("./assets/3d_models/sphere_ring.obj").unwrap().get_mesh();
+
+        let registry = ShaderRegistry::with_planets();
+        for planet in registry.names() {
+            let program = registry.get(planet).unwrap();
+            let uniforms = planet_uniforms(program, width as f32, height as f32);
+            let mut serial = Framebuffer::new(width, height);
+            let mut tiled = Framebuffer::new(width, height);
+            render_with_threads(&mut serial, &uniforms, &sphere, program, 1);
+            render_with_threads(&mut tiled, &uniforms, &sphere, program, 4);
+
+            assert!(serial.zbuffer.iter().any(|depth| depth.is_finite()), "planet {}", planet);
+            assert_eq!(serial.color_array_to_u32(), tiled.color_array_to_u32(), "planet {}", planet);
+            assert_eq!(serial.zbuffer, tiled.zbuffer, "planet {}", planet);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::f32::consts::PI;
+
+use nalgebra_glm::Vec3;
+use crate::camera::Camera;
+use crate::obj::PlanetMeshes;
+use crate::offscreen::{render_offscreen, OffscreenSettings};
+use crate::registry::ShaderRegistry;
+use crate::screen::framebuffer::Framebuffer;
+
+// An animation where the camera orbits the planet while `uniforms.time` advances
+#[derive(Debug, Clone)]
+pub struct TurntableSettings {
+    // Camera, planet and resolution of the first frame
+    pub start: OffscreenSettings,
+    pub frames: u32,
+    // How far `uniforms.time` advances between animation frames
+    pub time_step: u32,
+    // Total camera yaw over the whole animation
+    pub orbit_degrees: f32,
+}
+
+impl Default for TurntableSettings {
+    fn default() -> Self {
+        TurntableSettings {
+            start: OffscreenSettings::default(),
+            frames: 60,
+            time_step: 1,
+            orbit_degrees: 360.0,
+        }
+    }
+}
+
+pub fn render_turntable(settings: &TurntableSettings, registry: &ShaderRegistry, meshes: &PlanetMeshes) -> Result<Vec<Framebuffer>, String> {
+    let mut camera = Camera::new(settings.start.eye, settings.start.center, Vec3::new(0.0, 1.0, 0.0));
+    let yaw_step = settings.orbit_degrees * PI / 180.0 / settings.frames.max(1) as f32;
+
+    let mut frames = Vec::with_capacity(settings.frames as usize);
+    for index in 0..settings.frames {
+        let frame_settings = OffscreenSettings {
+            eye: camera.eye,
+            frame: settings.start.frame + index * settings.time_step,
+            ..settings.start.clone()
+        };
+        frames.push(render_offscreen(&frame_settings, registry, meshes)?);
+        camera.orbit(yaw_step, 0.0);
+    }
+    Ok(frames)
+}
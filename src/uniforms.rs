@@ -1,7 +1,15 @@
-use crate::fragments::{triangle_fill, Fragment};
-use crate::shader::{earth_shader, jupiter_shader, mercury_shader, neptune_shader, saturn_shader, sun_shader, venus_shader};
+use std::ops::AddAssign;
+use std::thread;
+
+use crate::bounding_box::edge_function;
+use crate::clipping::{clip_triangle, is_inside_frustum, project_to_screen};
+use crate::fragments::triangle_fill;
+use crate::mesh::Mesh;
+use crate::registry::ShaderProgram;
+use crate::screen::framebuffer::Framebuffer;
+use crate::shader::FragmentShader;
+use crate::tiles::rasterize_tiles;
 use crate::vertex::Vertex;
-use crate::{screen::framebuffer::Framebuffer, shader::{vertex_shader}};
 use fastnoise_lite::FastNoiseLite;
 use nalgebra_glm::{Mat4, Vec3};
 
@@ -13,52 +21,199 @@ pub struct Uniforms {
     pub light_dir: Vec3,
     pub time: u32,
     pub noise: FastNoiseLite,
-    pub planet: u8
+    pub cull_mode: CullMode,
+    pub front_face: Winding,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+// Vertex order (as seen by the camera) that makes a triangle front-facing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    CounterClockwise,
+    Clockwise,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    pub triangles: usize,
+    // Triangles entirely outside the view volume; ones split by clipping
+    // are still drawn and not counted here
+    pub discarded: usize,
+    pub culled: usize,
+    // Zero-area triangles, which cover no pixels whatever the cull mode
+    pub degenerate: usize,
+    pub rasterized: usize,
+}
+
+impl AddAssign for RenderStats {
+    fn add_assign(&mut self, other: RenderStats) {
+        self.triangles += other.triangles;
+        self.discarded += other.discarded;
+        self.culled += other.culled;
+        self.degenerate += other.degenerate;
+        self.rasterized += other.rasterized;
+    }
 }
-pub fn render(framebuffer: &mut Framebuffer, uniforms: &Uniforms, vertex_array: &[Vertex]) {
-    // 1. Vertex shader stage
-    let mut shaded_vertices: Vec<Vertex> = Vec::new();
-    for vertex in vertex_array {
-        shaded_vertices.push(vertex_shader(vertex, uniforms))
+
+// The viewport flips y, so a counter-clockwise triangle has a positive
+// edge_function area once it reaches screen space
+fn is_culled(area: f32, uniforms: &Uniforms) -> bool {
+    let counter_clockwise = area > 0.0;
+    let front_facing = counter_clockwise == (uniforms.front_face == Winding::CounterClockwise);
+    match uniforms.cull_mode {
+        CullMode::None => false,
+        CullMode::Back => !front_facing,
+        CullMode::Front => front_facing,
+    }
+}
+
+pub fn render(framebuffer: &mut Framebuffer, uniforms: &Uniforms, mesh: &Mesh, program: &ShaderProgram) -> RenderStats {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    render_with_threads(framebuffer, uniforms, mesh, program, threads)
+}
+
+// `threads == 1` runs the serial rasterizer, anything above uses the tiled one
+pub fn render_with_threads(framebuffer: &mut Framebuffer, uniforms: &Uniforms, mesh: &Mesh, program: &ShaderProgram, threads: usize) -> RenderStats {
+    let mut stats = RenderStats::default();
+
+    // 1. Vertex shader stage, once per unique vertex. Vertices inside the
+    // frustum are also projected here, once, since every triangle using them
+    // needs the same screen position.
+    let mut vertices: Vec<Vertex> = mesh.vertices.iter()
+        .map(|vertex| program.vertex.shade(vertex, uniforms))
+        .collect();
+    let inside: Vec<bool> = vertices.iter().map(|vertex| is_inside_frustum(&vertex.clip_position)).collect();
+    for (vertex, _) in vertices.iter_mut().zip(&inside).filter(|(_, inside)| **inside) {
+        project_to_screen(vertex, uniforms);
+    }
+
+    // 2. Primitive Assembly stage (only triangles), by index
+    // 3. Clipping stage, followed by perspective division and viewport mapping.
+    // Triangles crossing the frustum are replaced by clipped ones whose new
+    // vertices are appended after the mesh's.
+    let mut triangles: Vec<[u32; 3]> = Vec::with_capacity(mesh.triangle_count());
+    for tri in mesh.indices.chunks_exact(3) {
+        let tri = [tri[0], tri[1], tri[2]];
+        if tri.iter().all(|&index| inside[index as usize]) {
+            triangles.push(tri);
+            continue;
+        }
+
+        let [v1, v2, v3] = tri.map(|index| &vertices[index as usize]);
+        let clipped_tris = clip_triangle(v1, v2, v3);
+        if clipped_tris.is_empty() {
+            stats.discarded += 1;
+        }
+        for clipped in clipped_tris {
+            let first = vertices.len() as u32;
+            for mut vertex in clipped {
+                project_to_screen(&mut vertex, uniforms);
+                vertices.push(vertex);
+            }
+            triangles.push([first, first + 1, first + 2]);
+        }
     }
+    stats.triangles = mesh.triangle_count();
 
-    // 2. Primitive Assembly stage (only triangles)
-    let len = shaded_vertices.len();
-    let mut triangles= Vec::new();
-    
-    for i in (0..shaded_vertices.len()).step_by(3) {
-        if i + 2 < shaded_vertices.len() {
-            triangles.push([
-                shaded_vertices[i].clone(),
-                shaded_vertices[i + 1].clone(),
-                shaded_vertices[i + 2].clone(),
-            ]);
+    // 4. Face culling stage
+    let mut visible_triangles = Vec::new();
+    for tri in triangles {
+        let [v1, v2, v3] = tri.map(|index| &vertices[index as usize].transformed_position);
+        let area = edge_function(v1, v2, v3);
+        if area == 0.0 {
+            stats.degenerate += 1;
+        } else if is_culled(area, uniforms) {
+            stats.culled += 1;
+        } else {
+            visible_triangles.push(tri);
         }
     }
+    stats.rasterized = visible_triangles.len();
 
-    // Rasterization Stage
+    // 5. Rasterization and Fragment Processing stages
+    if threads > 1 {
+        rasterize_tiles(framebuffer, &vertices, &visible_triangles, uniforms, program.fragment.as_ref(), threads);
+    } else {
+        rasterize(framebuffer, &vertices, &visible_triangles, uniforms, program.fragment.as_ref());
+    }
+    stats
+}
 
-    let mut fragments: Vec<Fragment> = Vec::new();
+fn rasterize(framebuffer: &mut Framebuffer, vertices: &[Vertex], triangles: &[[u32; 3]], uniforms: &Uniforms, fragment_shader: &dyn FragmentShader) {
+    let derivatives = fragment_shader.needs_derivatives();
     for tri in triangles {
-        fragments.extend(triangle_fill(&tri[0], &tri[1], &tri[2], uniforms));
+        triangle_fill(tri.map(|index| &vertices[index as usize]), uniforms, framebuffer, (0, 0), derivatives, |fragment| {
+            fragment_shader.shade(fragment, uniforms)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+    use crate::fragments::Fragment;
+    use crate::matrix::create_viewport_matrix;
+    use crate::screen::color::Color;
+    use crate::shader::vertex_shader;
+
+    // One counter-clockwise and one clockwise triangle, as seen by the camera,
+    // plus one with all three corners on a line
+    fn two_windings() -> Mesh {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let corner = |x: f32, y: f32| Vertex::new(Vec3::new(x, y, 0.0), normal, Vec2::zeros());
+        Mesh::new(
+            vec![
+                corner(-0.9, -0.9), corner(-0.1, -0.9), corner(-0.5, -0.1),
+                corner(0.1, -0.9), corner(0.5, -0.1), corner(0.9, -0.9),
+                corner(0.0, 0.2), corner(0.0, 0.5), corner(0.0, 0.8),
+            ],
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8],
+        )
     }
 
-    // Fragment Processing Stage
-    for fragment in fragments {
-        let x = fragment.position.x as usize;
-        let y = fragment.position.y as usize;
-        let shaded_color = match uniforms.planet {
-            1=> sun_shader(&fragment, uniforms),
-            2=> mercury_shader(&fragment, uniforms),
-            3=> venus_shader(&fragment, uniforms),
-            4=> earth_shader(&fragment, uniforms),
-            5=> jupiter_shader(&fragment, uniforms),
-            6=> saturn_shader(&fragment, uniforms),
-            7=> neptune_shader(&fragment, uniforms),
-            _=> sun_shader(&fragment, uniforms)
-        };
-        let color = shaded_color.to_hex();
-        framebuffer.set_current_color(color);
-        framebuffer.point(x, y, fragment.depth);
+    fn uniforms(cull_mode: CullMode, front_face: Winding) -> Uniforms {
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: create_viewport_matrix(32.0, 32.0),
+            light_dir: Vec3::new(0.0, 0.0, 1.0),
+            time: 0,
+            noise: FastNoiseLite::new(),
+            cull_mode,
+            front_face,
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cull_modes_follow_the_front_face_winding() {
+        let mesh = two_windings();
+        let program = ShaderProgram::new("flat", |_: &Fragment, _: &Uniforms| Color::new(255, 255, 255), FastNoiseLite::new)
+            .with_vertex_shader(vertex_shader);
+        for front_face in [Winding::CounterClockwise, Winding::Clockwise] {
+            for (cull_mode, culled) in [(CullMode::None, 0), (CullMode::Back, 1), (CullMode::Front, 1)] {
+                let mut framebuffer = Framebuffer::new(32, 32);
+                let stats = render_with_threads(&mut framebuffer, &uniforms(cull_mode, front_face), &mesh, &program, 1);
+                assert_eq!((stats.culled, stats.degenerate, stats.rasterized), (culled, 1, 2 - culled), "{:?} {:?}", cull_mode, front_face);
+
+                // Which of the two is left: the left one is counter-clockwise
+                let drawn = |x: usize| framebuffer.buffer[24 * 32 + x].to_hex() == 0xffffff;
+                let keeps_front = cull_mode != CullMode::Front;
+                let left_is_front = front_face == Winding::CounterClockwise;
+                if cull_mode != CullMode::None {
+                    assert_eq!(drawn(8), keeps_front == left_is_front);
+                    assert_eq!(drawn(24), keeps_front != left_is_front);
+                } else {
+                    assert!(drawn(8) && drawn(24));
+                }
+            }
+        }
+    }
+}
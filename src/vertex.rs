@@ -1,4 +1,4 @@
-use nalgebra_glm::{Vec2, Vec3};
+use nalgebra_glm::{Vec2, Vec3, Vec4};
 use crate::screen::color::Color;
 
 #[derive(Clone, Debug)]
@@ -7,8 +7,10 @@ pub struct Vertex {
   pub normal: Vec3,
   pub tex_coords: Vec2,
   pub color: Color,
+  pub clip_position: Vec4,
   pub transformed_position: Vec3,
   pub transformed_normal: Vec3,
+  pub inv_w: f32,
 }
 
 impl Vertex {
@@ -18,8 +20,10 @@ impl Vertex {
       normal,
       tex_coords,
       color: Color::black(),
+      clip_position: Vec4::new(position.x, position.y, position.z, 1.0),
       transformed_position: position,
       transformed_normal: normal,
+      inv_w: 1.0,
     }
   }
 
@@ -29,14 +33,17 @@ impl Vertex {
       normal: Vec3::new(0.0, 0.0, 0.0),
       tex_coords: Vec2::new(0.0, 0.0),
       color,
+      clip_position: Vec4::new(position.x, position.y, position.z, 1.0),
       transformed_position: Vec3::new(0.0, 0.0, 0.0),
       transformed_normal: Vec3::new(0.0, 0.0, 0.0),
+      inv_w: 1.0,
     }
   }
 
-  pub fn set_transformed(&mut self, position: Vec3, normal: Vec3) {
+  pub fn set_transformed(&mut self, position: Vec3, normal: Vec3, inv_w: f32) {
     self.transformed_position = position;
     self.transformed_normal = normal;
+    self.inv_w = inv_w;
   }
 }
 
@@ -47,8 +54,10 @@ impl Default for Vertex {
       normal: Vec3::new(0.0, 1.0, 0.0),
       tex_coords: Vec2::new(0.0, 0.0),
       color: Color::black(),
+      clip_position: Vec4::new(0.0, 0.0, 0.0, 1.0),
       transformed_position: Vec3::new(0.0, 0.0, 0.0),
       transformed_normal: Vec3::new(0.0, 1.0, 0.0),
+      inv_w: 1.0,
     }
   }
 }
\ No newline at end of file
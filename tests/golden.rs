@@ -0,0 +1,160 @@
+// Golden-image regression tests: every planet is rendered at a fixed camera
+// and time and compared against the reference PNGs in tests/golden.
+//
+// After an intended change to a shader's look, regenerate the references with
+//     UPDATE_GOLDEN=1 cargo test --test golden
+// and review the new images before committing them.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use nalgebra_glm::Vec3;
+use cg_custom_shaders::obj::PlanetMeshes;
+use cg_custom_shaders::offscreen::{render_offscreen, OffscreenSettings};
+use cg_custom_shaders::registry::ShaderRegistry;
+use cg_custom_shaders::screen::color::Color;
+use cg_custom_shaders::screen::framebuffer::Framebuffer;
+use cg_custom_shaders::screen::image::{load_png, save_png};
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 96;
+const FRAME: u32 = 10;
+
+// Largest per-channel difference that still counts as the same pixel
+const CHANNEL_TOLERANCE: u8 = 2;
+// Pixels allowed past the tolerance, to absorb rounding on triangle edges
+const MAX_MISMATCHED_PIXELS: usize = 4;
+
+fn golden_path(planet: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", planet))
+}
+
+fn diff_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn render_planet(planet: &str) -> Framebuffer {
+    render_with(planet, &ShaderRegistry::with_planets())
+}
+
+fn render_with(planet: &str, registry: &ShaderRegistry) -> Framebuffer {
+    let settings = OffscreenSettings {
+        planet: planet.to_string(),
+        eye: Vec3::new(0.0, 0.6, -2.4),
+        width: WIDTH,
+        height: HEIGHT,
+        frame: FRAME,
+        ..OffscreenSettings::default()
+    };
+    let meshes = PlanetMeshes::load("./assets/3d_models").expect("Failed to load obj");
+    render_offscreen(&settings, registry, &meshes).unwrap()
+}
+
+fn channel_difference(a: &Color, b: &Color) -> u8 {
+    a.r.abs_diff(b.r).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b))
+}
+
+// Mismatched pixels in red over a dimmed copy of the actual render
+fn diff_image(actual: &Framebuffer, expected: &Framebuffer) -> Framebuffer {
+    let mut diff = Framebuffer::new(actual.width, actual.height);
+    for (i, (a, e)) in actual.buffer.iter().zip(&expected.buffer).enumerate() {
+        diff.buffer[i] = if channel_difference(a, e) > CHANNEL_TOLERANCE {
+            Color::new(255, 0, 0)
+        } else {
+            *a * 0.3
+        };
+    }
+    diff
+}
+
+fn check_golden(planet: &str) {
+    let actual = render_planet(planet);
+    let path = golden_path(planet);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        save_png(&actual, &path).unwrap();
+        return;
+    }
+
+    let expected = load_png(&path).unwrap_or_else(|e| {
+        panic!("missing golden image {} ({}); run with UPDATE_GOLDEN=1 to create it", path.display(), e)
+    });
+    assert_eq!(
+        (actual.width, actual.height), (expected.width, expected.height),
+        "{} golden image has a different size", planet
+    );
+
+    let mismatched = actual.buffer.iter().zip(&expected.buffer)
+        .filter(|(a, e)| channel_difference(a, e) > CHANNEL_TOLERANCE)
+        .count();
+    if mismatched > MAX_MISMATCHED_PIXELS {
+        let dir = diff_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let actual_path = dir.join(format!("{}_actual.png", planet));
+        let diff_path = dir.join(format!("{}_diff.png", planet));
+        save_png(&actual, &actual_path).unwrap();
+        save_png(&diff_image(&actual, &expected), &diff_path).unwrap();
+        panic!(
+            "{}: {} pixels differ from {} (see {} and {})",
+            planet, mismatched, path.display(), actual_path.display(), diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn sun_matches_golden() {
+    check_golden("sun");
+}
+
+#[test]
+fn mercury_matches_golden() {
+    check_golden("mercury");
+}
+
+#[test]
+fn venus_matches_golden() {
+    check_golden("venus");
+}
+
+#[test]
+fn earth_matches_golden() {
+    check_golden("earth");
+}
+
+#[test]
+fn jupiter_matches_golden() {
+    check_golden("jupiter");
+}
+
+#[test]
+fn saturn_matches_golden() {
+    check_golden("saturn");
+}
+
+#[test]
+fn neptune_matches_golden() {
+    check_golden("neptune");
+}
+
+#[test]
+fn moon_matches_golden() {
+    check_golden("moon");
+}
+
+#[test]
+fn every_registered_planet_has_a_golden_image() {
+    for planet in ShaderRegistry::with_planets().names() {
+        assert!(golden_path(planet).exists(), "no golden image for {}", planet);
+    }
+}
+
+#[test]
+fn planet_graphs_render_like_the_shaders() {
+    let graphs = ShaderRegistry::with_planet_graphs();
+    for planet in ShaderRegistry::with_planets().names() {
+        let expected = render_planet(planet);
+        let actual = render_with(planet, &graphs);
+        let identical = actual.buffer.iter().zip(&expected.buffer).all(|(a, e)| channel_difference(a, e) == 0);
+        assert!(identical, "{} graph renders differently", planet);
+    }
+}